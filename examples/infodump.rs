@@ -55,6 +55,12 @@ fn main() -> Result<(), Box<dyn Error>> {
                 }
             };
             println!("    {} surface attributes", attribs.len());
+            if !attribs.drm_format_modifiers().is_empty() {
+                println!(
+                    "    DRM format modifiers: {:#018x?}",
+                    attribs.drm_format_modifiers()
+                );
+            }
             for attrib in attribs {
                 print!("    - {:?} ", attrib.ty());
                 if attrib.flags().is_empty() {
@@ -136,6 +142,24 @@ fn main() -> Result<(), Box<dyn Error>> {
         println!("Empty pipeline capabilities:");
         println!("- Pipeline Flags: {:?}", caps.pipeline_flags());
         println!("- Filter Flags: {:?}", caps.filter_flags());
+        println!("- Rotation Flags: {:?}", caps.rotation_flags());
+        println!("- Blend Flags: {:?}", caps.blend_flags());
+        println!("- Mirror Flags: {:?}", caps.mirror_flags());
+        println!("- Additional Outputs: {}", caps.num_additional_outputs());
+        println!(
+            "- Input Size: {}x{} - {}x{}",
+            caps.min_input_width(),
+            caps.min_input_height(),
+            caps.max_input_width(),
+            caps.max_input_height(),
+        );
+        println!(
+            "- Output Size: {}x{} - {}x{}",
+            caps.min_output_width(),
+            caps.min_output_height(),
+            caps.max_output_width(),
+            caps.max_output_height(),
+        );
         println!(
             "- Input Color Standards: {:?}",
             caps.input_color_standards()
@@ -2,15 +2,8 @@ use std::{num::NonZeroU32, rc::Rc, time::Instant};
 
 use anyhow::bail;
 use fev::{
-    buffer::{Buffer, BufferType},
-    config::Config,
-    context::Context,
     display::Display,
-    image::{Image, ImageFormat},
-    jpeg::{JpegDecodeSession, JpegInfo},
-    surface::{ExportSurfaceFlags, Surface},
-    vpp::{ColorProperties, ColorStandardType, ProcPipelineParameterBuffer, SourceRange},
-    Entrypoint, PixelFormat, Profile,
+    jpeg::{JpegInfo, JpegToRgbaPipeline},
 };
 use winit::{
     dpi::PhysicalSize,
@@ -71,69 +64,19 @@ fn main() -> anyhow::Result<()> {
 
     let display = Display::new(win.clone())?;
 
-    let mut context = JpegDecodeSession::new(&display, width, height)?;
-    let prime = context
-        .surface()
-        .export_prime(ExportSurfaceFlags::SEPARATE_LAYERS | ExportSurfaceFlags::READ)?;
-    log::debug!("PRIME export: {prime:#?}");
-
-    let config = Config::new(&display, Profile::None, Entrypoint::VideoProc)?;
-    let mut vpp_context = Context::new(&config, width.into(), height.into())?;
-    let mut vpp_surface =
-        Surface::with_pixel_format(&display, width.into(), height.into(), PixelFormat::RGBA)?;
-
-    let mut image = Image::new(
-        &display,
-        ImageFormat::new(PixelFormat::RGBA),
-        jpeg_info.width().into(),
-        jpeg_info.height().into(),
-    )?;
+    // NB: not all implementations support converting color standards (eg. Mesa).
+    // such implementations will typically output an image that is brighter than the reference data.
+    let mut pipeline = JpegToRgbaPipeline::new(&display)?;
 
     log::debug!("<decode>");
     let start = Instant::now();
-    let surf = context.decode(&jpeg)?;
+    let image = pipeline.decode(&jpeg)?;
     log::debug!("</decode> took {:?}", start.elapsed());
 
-    // Use VPP to convert the surface to RGBA.
-    let mut pppbuf = ProcPipelineParameterBuffer::new(surf);
-
-    // The input color space is the JPEG color space
-    let input_props = ColorProperties::new().with_color_range(SourceRange::FULL);
-    pppbuf.set_input_color_properties(input_props);
-    pppbuf.set_input_color_standard(ColorStandardType::BT601);
-    // The output color space is 8-bit non-linear sRGB
-    let output_props = ColorProperties::new().with_color_range(SourceRange::FULL);
-    pppbuf.set_output_color_properties(output_props);
-    pppbuf.set_output_color_standard(ColorStandardType::SRGB);
-    // NB: not all implementations support converting color standards (eg. Mesa).
-    // such implementations  will typically output an image that is brighter than the reference data.
-
-    let mut pppbuf = Buffer::new_param(&vpp_context, BufferType::ProcPipelineParameter, pppbuf)?;
-
-    let mut picture = vpp_context.begin_picture(&mut vpp_surface)?;
-    unsafe {
-        picture.render_picture(&mut pppbuf)?;
-        picture.end_picture()?;
-    }
-
-    drop(pppbuf);
-
-    vpp_surface.copy_to_image(&mut image)?;
-    let mapping = image.map()?;
-
-    log::debug!("{} byte output", mapping.len());
-
-    let start = Instant::now();
-    let data = mapping.to_vec();
-    log::trace!("copy from VABuffer took {:?}", start.elapsed());
-    let start = Instant::now();
-    let data = data.to_vec();
-    log::trace!("vec copy took {:?}", start.elapsed());
-
     let start = Instant::now();
-    let decoded_data: Vec<_> = data
+    let decoded_data: Vec<_> = image
+        .data()
         .chunks(4)
-        .take(jpeg_info.width() as usize * jpeg_info.height() as usize) // ignore trailing padding bytes
         .map(|pix| {
             let [r, g, b, _a] = [pix[0], pix[1], pix[2], pix[3]].map(u32::from);
             r << 16 | g << 8 | b
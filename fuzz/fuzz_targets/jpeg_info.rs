@@ -0,0 +1,14 @@
+#![no_main]
+
+use fev::jpeg::JpegInfo;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((info, support)) = JpegInfo::probe(data) {
+        let _ = format!("{support}");
+        info.rt_format();
+        info.pixel_format();
+    }
+
+    let _ = JpegInfo::new(data);
+});
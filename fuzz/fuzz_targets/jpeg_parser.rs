@@ -0,0 +1,17 @@
+#![no_main]
+
+use fev::jpeg::parser::{icc_profile, JpegParser, SegmentKind};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut parser = JpegParser::new(data);
+    while let Ok(Some(segment)) = parser.next_segment() {
+        // Also exercise the metadata accessors and `Debug` impls.
+        let _ = format!("{:?}", segment.kind);
+        if let SegmentKind::Exif(exif) = segment.kind {
+            exif.orientation();
+        }
+    }
+
+    let _ = icc_profile(data);
+});
@@ -7,6 +7,7 @@ use std::{
     ops::{Deref, DerefMut},
     ptr,
     sync::Arc,
+    time::Duration,
 };
 
 use bytemuck::{AnyBitPattern, NoUninit, Pod};
@@ -15,6 +16,7 @@ use crate::{
     check, check_log,
     context::Context,
     display::DisplayOwner,
+    error::VAError,
     raw::{VABufferID, VA_TIMEOUT_INFINITE},
     Result,
 };
@@ -235,6 +237,26 @@ impl<T> Buffer<T> {
             )
         }
     }
+
+    /// Waits until the operation producing this [`Buffer`]'s contents (eg. an encode operation
+    /// writing to a coded buffer) has completed, or `timeout` has elapsed.
+    ///
+    /// Returns `true` if the operation has completed, and `false` if the timeout has elapsed
+    /// first.
+    pub fn sync_timeout(&mut self, timeout: Duration) -> Result<bool> {
+        let timeout_ns = u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX);
+        let status = unsafe {
+            self.raw
+                .d
+                .libva
+                .vaSyncBuffer(self.raw.d.raw, self.raw.id, timeout_ns)
+        };
+        if status == VAError::ERROR_TIMEDOUT {
+            return Ok(false);
+        }
+        check("vaSyncBuffer", status)?;
+        Ok(true)
+    }
 }
 
 /// A handle to the memory-mapped data of a [`Buffer`].
@@ -76,6 +76,15 @@ pub struct ConfigAttrib {
 }
 
 impl ConfigAttrib {
+    /// Creates a [`ConfigAttrib`] of the given type, with a raw attribute value.
+    #[inline]
+    pub fn new(attrib_type: ConfigAttribType, value: u32) -> Self {
+        Self {
+            type_: attrib_type,
+            value,
+        }
+    }
+
     fn zeroed() -> Self {
         unsafe { mem::zeroed() }
     }
@@ -168,7 +177,17 @@ impl Config {
                 ),
             )?;
             attribs.set_len(num_attribs as usize);
-            Ok(SurfaceAttributes { vec: attribs })
+
+            // Pointer values refer to driver memory, so copy them out while they are valid.
+            let enums = attribs
+                .iter()
+                .filter_map(|attrib| attrib.copy_value())
+                .collect();
+
+            Ok(SurfaceAttributes {
+                vec: attribs,
+                enums,
+            })
         }
     }
 
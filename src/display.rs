@@ -14,9 +14,10 @@ use raw_window_handle::{HasDisplayHandle, RawDisplayHandle};
 
 use crate::{
     check, check_log,
+    config::{ConfigAttrib, ConfigAttribType},
     dlopen::{libva, libva_drm, libva_wayland, libva_win32, libva_x11},
     image::{ImageFormat, ImageFormats},
-    raw::{VADisplay, VA_PADDING_LOW},
+    raw::{VADisplay, VA_ATTRIB_NOT_SUPPORTED, VA_PADDING_LOW},
     subpicture::{SubpictureFlags, SubpictureFormats},
     Entrypoint, Entrypoints, Error, Profile, Profiles, Result,
 };
@@ -333,6 +334,29 @@ impl Display {
         Ok(Entrypoints { vec: entrypoints })
     }
 
+    /// Queries the value of a config attribute for the given [`Profile`] and [`Entrypoint`].
+    ///
+    /// The meaning of the returned raw value depends on the [`ConfigAttribType`]. Returns [`None`]
+    /// if the attribute is not supported by the implementation.
+    pub fn query_config_attribute(
+        &self,
+        profile: Profile,
+        entrypoint: Entrypoint,
+        attrib_type: ConfigAttribType,
+    ) -> Result<Option<u32>> {
+        let mut attrib = ConfigAttrib::new(attrib_type, 0);
+        unsafe {
+            check(
+                "vaGetConfigAttributes",
+                self.d
+                    .libva
+                    .vaGetConfigAttributes(self.d.raw, profile, entrypoint, &mut attrib, 1),
+            )?;
+        }
+        let value = attrib.raw_value();
+        Ok((value != VA_ATTRIB_NOT_SUPPORTED).then_some(value))
+    }
+
     /// Queries the supported [`ImageFormat`]s.
     pub fn query_image_formats(&self) -> Result<ImageFormats> {
         unsafe {
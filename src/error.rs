@@ -7,7 +7,7 @@ use std::{
     str::Utf8Error,
 };
 
-use crate::dlopen::libva;
+use crate::{dlopen::libva, jpeg::parser::JpegError};
 
 ffi_enum! {
     pub(crate) enum VAStatus: c_int {
@@ -95,6 +95,7 @@ pub(crate) enum Repr {
     Utf8Error(Utf8Error),
     TryFromIntError(TryFromIntError),
     HandleError(raw_window_handle::HandleError),
+    Jpeg(JpegError),
     Other(String),
     Static(&'static Error),
 }
@@ -111,6 +112,12 @@ impl From<TryFromIntError> for Repr {
     }
 }
 
+impl From<JpegError> for Repr {
+    fn from(v: JpegError) -> Self {
+        Self::Jpeg(v)
+    }
+}
+
 impl From<String> for Repr {
     fn from(v: String) -> Self {
         Self::Other(v)
@@ -144,6 +151,15 @@ impl Error {
         }
     }
 
+    /// If this [`Error`] was caused by a malformed JPEG image, returns the underlying
+    /// [`JpegError`].
+    pub fn as_jpeg(&self) -> Option<&JpegError> {
+        match &self.repr {
+            Repr::Jpeg(e) => Some(e),
+            _ => None,
+        }
+    }
+
     pub(crate) fn from(e: impl Into<Repr>) -> Self {
         Self { repr: e.into() }
     }
@@ -199,6 +215,7 @@ impl fmt::Debug for Error {
             Repr::Utf8Error(e) => e.fmt(f),
             Repr::TryFromIntError(e) => e.fmt(f),
             Repr::HandleError(e) => e.fmt(f),
+            Repr::Jpeg(e) => e.fmt(f),
             Repr::Other(s) => s.fmt(f),
             Repr::Static(e) => e.fmt(f),
         }
@@ -226,6 +243,7 @@ impl fmt::Display for Error {
             Repr::Utf8Error(e) => e.fmt(f),
             Repr::TryFromIntError(e) => e.fmt(f),
             Repr::HandleError(e) => e.fmt(f),
+            Repr::Jpeg(e) => e.fmt(f),
             Repr::Other(e) => e.fmt(f),
             Repr::Static(e) => e.fmt(f),
         }
@@ -233,3 +251,10 @@ impl fmt::Display for Error {
 }
 
 impl std::error::Error for Error {}
+
+impl From<JpegError> for Error {
+    #[inline]
+    fn from(e: JpegError) -> Self {
+        Self { repr: e.into() }
+    }
+}
@@ -0,0 +1,315 @@
+//! Futures that wait for VA-API operations to complete.
+//!
+//! This module is only available with the `async` Cargo feature.
+//!
+//! The futures work with any async runtime. They poll the driver without blocking (using
+//! `vaSyncSurface2` and `vaSyncBuffer` with a zero timeout), and are woken up again by a shared
+//! background thread after a delay that grows while the operation is still in progress. This
+//! avoids tying up a runtime worker thread for every operation in flight.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    future::Future,
+    pin::Pin,
+    sync::{mpsc, OnceLock},
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    buffer::Buffer,
+    jpeg::JpegDecodeSession,
+    surface::Surface,
+    vpp::{Deinterlacer, Hdr10Metadata, ToneMapper},
+    Result,
+};
+
+const MIN_DELAY: Duration = Duration::from_micros(50);
+const MAX_DELAY: Duration = Duration::from_millis(4);
+
+/// Schedules wakeups with exponentially increasing delays.
+struct Backoff {
+    delay: Duration,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Self { delay: MIN_DELAY }
+    }
+
+    /// Arranges for `waker` to be woken after the current delay, and increases the delay.
+    fn wake_later(&mut self, waker: &Waker) {
+        let deadline = Instant::now() + self.delay;
+        self.delay = (self.delay * 2).min(MAX_DELAY);
+        match timer() {
+            Some(timer) => {
+                // The timer thread never exits, so this cannot fail.
+                timer
+                    .send(TimerEntry {
+                        deadline,
+                        waker: waker.clone(),
+                    })
+                    .ok();
+            }
+            // Without a timer thread, fall back to polling again right away.
+            None => waker.wake_by_ref(),
+        }
+    }
+}
+
+struct TimerEntry {
+    deadline: Instant,
+    waker: Waker,
+}
+
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for TimerEntry {}
+
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TimerEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+/// Returns the channel of the timer thread, spawning it on first use.
+///
+/// Returns [`None`] if the thread could not be spawned.
+fn timer() -> Option<&'static mpsc::Sender<TimerEntry>> {
+    static TIMER: OnceLock<Option<mpsc::Sender<TimerEntry>>> = OnceLock::new();
+
+    TIMER
+        .get_or_init(|| {
+            let (sender, receiver) = mpsc::channel();
+            match thread::Builder::new()
+                .name("fev-sync-timer".into())
+                .spawn(move || run_timer(receiver))
+            {
+                Ok(_) => Some(sender),
+                Err(e) => {
+                    log::error!("failed to spawn timer thread, futures will busy-poll: {e}");
+                    None
+                }
+            }
+        })
+        .as_ref()
+}
+
+fn run_timer(receiver: mpsc::Receiver<TimerEntry>) {
+    let mut queue = BinaryHeap::<Reverse<TimerEntry>>::new();
+    loop {
+        let now = Instant::now();
+        while let Some(Reverse(entry)) = queue.pop() {
+            if entry.deadline > now {
+                queue.push(Reverse(entry));
+                break;
+            }
+            entry.waker.wake();
+        }
+
+        let entry = match queue.peek() {
+            Some(Reverse(next)) => match receiver.recv_timeout(next.deadline - now) {
+                Ok(entry) => entry,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            },
+            None => match receiver.recv() {
+                Ok(entry) => entry,
+                Err(mpsc::RecvError) => return,
+            },
+        };
+        queue.push(Reverse(entry));
+    }
+}
+
+/// Future returned by [`Surface::synced`].
+#[must_use = "futures do nothing unless polled"]
+pub struct SurfaceSynced<'a> {
+    surface: &'a mut Surface,
+    backoff: Backoff,
+}
+
+impl Future for SurfaceSynced<'_> {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match this.surface.sync_timeout(Duration::ZERO) {
+            Ok(true) => Poll::Ready(Ok(())),
+            Ok(false) => {
+                this.backoff.wake_later(cx.waker());
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+/// Future returned by [`Buffer::synced`].
+#[must_use = "futures do nothing unless polled"]
+pub struct BufferSynced<'a, T> {
+    buffer: &'a mut Buffer<T>,
+    backoff: Backoff,
+}
+
+impl<T> Future for BufferSynced<'_, T> {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match this.buffer.sync_timeout(Duration::ZERO) {
+            Ok(true) => Poll::Ready(Ok(())),
+            Ok(false) => {
+                this.backoff.wake_later(cx.waker());
+                Poll::Pending
+            }
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+/// Async surface methods.
+impl Surface {
+    /// Returns a [`Future`] that completes once all pending operations on this [`Surface`] have
+    /// finished.
+    ///
+    /// This is the non-blocking equivalent of [`Surface::sync`].
+    pub fn synced(&mut self) -> SurfaceSynced<'_> {
+        SurfaceSynced {
+            surface: self,
+            backoff: Backoff::new(),
+        }
+    }
+}
+
+/// Async buffer methods.
+impl<T> Buffer<T> {
+    /// Returns a [`Future`] that completes once the operation producing this [`Buffer`]'s contents
+    /// has finished.
+    ///
+    /// This is the non-blocking equivalent of [`Buffer::sync`].
+    pub fn synced(&mut self) -> BufferSynced<'_, T> {
+        BufferSynced {
+            buffer: self,
+            backoff: Backoff::new(),
+        }
+    }
+}
+
+/// Async JPEG decoding.
+impl JpegDecodeSession {
+    /// Decodes a baseline JPEG, and waits for decoding to finish without blocking.
+    ///
+    /// See [`JpegDecodeSession::decode`].
+    pub async fn decode_async(&mut self, jpeg: &[u8]) -> Result<&mut Surface> {
+        let surface = self.decode(jpeg)?;
+        surface.synced().await?;
+        Ok(surface)
+    }
+}
+
+/// Async video processing.
+impl ToneMapper {
+    /// Tone maps `input` to SDR, and waits for the result to be written to `output` without
+    /// blocking.
+    ///
+    /// See [`ToneMapper::tone_map`].
+    pub async fn tone_map_async(
+        &mut self,
+        input: &Surface,
+        metadata: &Hdr10Metadata,
+        output: &mut Surface,
+    ) -> Result<()> {
+        self.tone_map(input, metadata, output)?;
+        output.synced().await
+    }
+}
+
+/// Async video processing.
+impl Deinterlacer {
+    /// Deinterlaces the current frame, and waits for both fields to be written to `first` and
+    /// `second` without blocking.
+    ///
+    /// See [`Deinterlacer::deinterlace`].
+    pub async fn deinterlace_async(
+        &mut self,
+        first: &mut Surface,
+        second: &mut Surface,
+    ) -> Result<()> {
+        self.deinterlace(first, second)?;
+        first.synced().await?;
+        second.synced().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::pin,
+        sync::Arc,
+        task::Wake,
+        thread::{self, Thread},
+    };
+
+    use crate::test::*;
+
+    use super::*;
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn backoff_wakes() {
+        // Stays pending for a few polls, relying on the timer thread to be woken up.
+        let mut backoff = Backoff::new();
+        let mut polls = 0;
+        block_on(std::future::poll_fn(|cx| {
+            polls += 1;
+            if polls < 5 {
+                backoff.wake_later(cx.waker());
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        }));
+        assert_eq!(backoff.delay, MIN_DELAY * 16);
+    }
+
+    #[test]
+    fn surface_synced() {
+        run_test(|display| {
+            let mut surface = test_surface(display);
+            block_on(surface.synced()).unwrap();
+            assert!(surface.is_ready().unwrap());
+        });
+    }
+}
@@ -9,6 +9,8 @@ use std::{
     vec,
 };
 
+use bytemuck::{AnyBitPattern, Pod};
+
 use crate::{
     buffer::Mapping,
     check, check_log,
@@ -188,8 +190,12 @@ pub struct Image {
 }
 
 impl Image {
-    pub fn new(
-        display: &Display,
+    pub fn new(display: &Display, format: ImageFormat, width: u32, height: u32) -> Result<Image> {
+        Self::create(&display.d, format, width, height)
+    }
+
+    pub(crate) fn create(
+        d: &Arc<DisplayOwner>,
         mut format: ImageFormat,
         width: u32,
         height: u32,
@@ -200,16 +206,11 @@ impl Image {
         unsafe {
             check(
                 "vaCreateImage",
-                display.d.libva.vaCreateImage(
-                    display.d.raw,
-                    &mut format,
-                    width,
-                    height,
-                    image.as_mut_ptr(),
-                ),
+                d.libva
+                    .vaCreateImage(d.raw, &mut format, width, height, image.as_mut_ptr()),
             )?;
             Ok(Image {
-                d: display.d.clone(),
+                d: d.clone(),
                 raw: image.assume_init(),
             })
         }
@@ -261,6 +262,189 @@ impl Image {
             capacity: self.raw.data_size as usize,
         })
     }
+
+    /// Returns the location and size of every plane of this [`Image`].
+    ///
+    /// The offset and pitch of each plane are determined by the driver, while the plane sizes are
+    /// derived from the image's [`PixelFormat`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the plane layout of the image's [`PixelFormat`] is unknown, or does not
+    /// match the number of planes reported by the driver.
+    pub fn planes(&self) -> Result<Vec<ImagePlane>> {
+        let format = self.pixel_format();
+        let sizes = format
+            .plane_sizes(self.width().into(), self.height().into())
+            .ok_or_else(|| {
+                Error::from(format!(
+                    "plane layout of pixel format {format:?} is unknown"
+                ))
+            })?;
+        if sizes.len() != self.raw.num_planes as usize {
+            return Err(Error::from(format!(
+                "{format:?} images have {} planes, but the driver reports {}",
+                sizes.len(),
+                self.raw.num_planes
+            )));
+        }
+
+        Ok(sizes
+            .iter()
+            .zip(self.raw.pitches.iter().zip(&self.raw.offsets))
+            .map(|(size, (&pitch, &offset))| ImagePlane {
+                offset,
+                pitch,
+                width: size.width,
+                height: size.height,
+                bytes_per_element: size.bytes_per_element,
+            })
+            .collect())
+    }
+
+    /// Maps the backing data of this [`Image`], providing access to the rows of each plane.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`Image::planes`] fails, if the planes reported by the driver do not fit
+    /// into the image's buffer, or if mapping the buffer fails.
+    pub fn map_planes(&mut self) -> Result<PlaneMapping<'_>> {
+        let planes = self.planes()?;
+        for (i, plane) in planes.iter().enumerate() {
+            // A zero pitch is only valid for empty rows, but would still break row iteration.
+            if u64::from(plane.pitch) < plane.row_size().max(1) {
+                return Err(Error::from(format!(
+                    "pitch of plane {i} ({}) is smaller than its row size ({})",
+                    plane.pitch,
+                    plane.row_size()
+                )));
+            }
+            let end = plane.end();
+            if end > u64::from(self.raw.data_size) {
+                return Err(Error::from(format!(
+                    "plane {i} ends at byte {end}, but the image buffer is only {} bytes long",
+                    self.raw.data_size
+                )));
+            }
+        }
+
+        Ok(PlaneMapping {
+            mapping: self.map()?,
+            planes,
+        })
+    }
+}
+
+/// Describes the location and size of a plane of an [`Image`].
+///
+/// Rows of the plane consist of [`ImagePlane::width`] elements. An element is a single sample for
+/// planar formats, a U/V sample pair for the chroma plane of semi-planar formats like
+/// [`PixelFormat::NV12`], a whole pixel for packed RGB formats, and a group of 2 pixels for packed
+/// YUV 4:2:2 formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImagePlane {
+    offset: u32,
+    pitch: u32,
+    width: u32,
+    height: u32,
+    bytes_per_element: u32,
+}
+
+impl ImagePlane {
+    /// Returns the byte offset of the plane in the image's buffer.
+    #[inline]
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Returns the distance between the starts of two consecutive rows, in bytes.
+    #[inline]
+    pub fn pitch(&self) -> u32 {
+        self.pitch
+    }
+
+    /// Returns the number of elements in each row.
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the number of rows.
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the size of an element in bytes.
+    #[inline]
+    pub fn bytes_per_element(&self) -> u32 {
+        self.bytes_per_element
+    }
+
+    /// Returns the size of the pixel data in each row in bytes, excluding padding.
+    fn row_size(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.bytes_per_element)
+    }
+
+    /// Returns the offset of the end of the last row's pixel data.
+    fn end(&self) -> u64 {
+        let rows_before_last = u64::from(self.height.saturating_sub(1));
+        u64::from(self.offset) + rows_before_last * u64::from(self.pitch) + self.row_size()
+    }
+}
+
+/// A mapped [`Image`], providing access to the rows of each of its planes.
+///
+/// Returned by [`Image::map_planes`].
+pub struct PlaneMapping<'a> {
+    mapping: Mapping<'a, u8>,
+    planes: Vec<ImagePlane>,
+}
+
+impl<'a> PlaneMapping<'a> {
+    /// Returns the layout of the mapped planes.
+    #[inline]
+    pub fn planes(&self) -> &[ImagePlane] {
+        &self.planes
+    }
+
+    /// Returns an iterator over the rows of plane `index`, excluding any padding at the end of the
+    /// rows.
+    ///
+    /// Each row is viewed as a slice of `T`, for example `u8` for the Y plane of an
+    /// [`PixelFormat::NV12`] image, `[u8; 2]` for its UV plane, or `[u8; 4]` for the pixels of a
+    /// packed RGB image.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `index` is out of bounds, or if the rows cannot be cast to slices of `T`
+    /// because of their size or alignment.
+    pub fn rows<T: AnyBitPattern>(&self, index: usize) -> impl ExactSizeIterator<Item = &[T]> {
+        let plane = self.planes[index];
+        let row_size = plane.row_size() as usize;
+        self.mapping[plane.offset as usize..]
+            .chunks(plane.pitch as usize)
+            .take(plane.height as usize)
+            .map(move |row| bytemuck::cast_slice(&row[..row_size]))
+    }
+
+    /// Returns an iterator over the mutable rows of plane `index`, excluding any padding at the end
+    /// of the rows.
+    ///
+    /// See [`PlaneMapping::rows`].
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `index` is out of bounds, or if the rows cannot be cast to slices of `T`
+    /// because of their size or alignment.
+    pub fn rows_mut<T: Pod>(&mut self, index: usize) -> impl ExactSizeIterator<Item = &mut [T]> {
+        let plane = self.planes[index];
+        let row_size = plane.row_size() as usize;
+        self.mapping[plane.offset as usize..]
+            .chunks_mut(plane.pitch as usize)
+            .take(plane.height as usize)
+            .map(move |row| bytemuck::cast_slice_mut(&mut row[..row_size]))
+    }
 }
 
 impl Drop for Image {
@@ -273,3 +457,53 @@ impl Drop for Image {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test::*;
+
+    use super::*;
+
+    #[test]
+    fn map_planes() {
+        run_test(|display| {
+            let mut surface = test_surface(display);
+            let mut image = Image::new(
+                display,
+                ImageFormat::new(TEST_PIXELFORMAT),
+                TEST_WIDTH,
+                TEST_HEIGHT,
+            )
+            .unwrap();
+            surface.copy_to_image(&mut image).unwrap();
+
+            let mapping = image.map_planes().unwrap();
+            assert_eq!(mapping.planes().len(), 1);
+            assert_eq!(mapping.planes()[0].width(), TEST_WIDTH);
+            let mut rows = mapping.rows::<[u8; 4]>(0);
+            assert_eq!(rows.len(), TEST_HEIGHT as usize);
+            let first_row = rows.next().unwrap();
+            assert_eq!(first_row.len(), TEST_WIDTH as usize);
+            assert_eq!(first_row[..3].as_flattened(), &TEST_DATA[..12]);
+        });
+    }
+
+    #[test]
+    fn nv12_planes() {
+        run_test(|display| {
+            let image = match Image::new(display, ImageFormat::new(PixelFormat::NV12), 64, 48) {
+                Ok(image) => image,
+                Err(e) => {
+                    log::warn!("skipping test; NV12 images are not supported: {e}");
+                    return;
+                }
+            };
+            let planes = image.planes().unwrap();
+            assert_eq!(planes.len(), 2);
+            assert_eq!((planes[0].width(), planes[0].height()), (64, 48));
+            assert_eq!((planes[1].width(), planes[1].height()), (32, 24));
+            assert_eq!(planes[1].bytes_per_element(), 2);
+            assert!(planes[1].offset() >= planes[0].pitch() * 48);
+        });
+    }
+}
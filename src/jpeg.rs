@@ -1,26 +1,35 @@
 //! JPEG-related types and utilities.
 
-mod parser;
+mod batch;
+mod decoder;
+mod mjpeg;
+pub mod parser;
+mod rgba;
 
 #[cfg(test)]
 mod tests;
 
-use std::{cmp, mem};
+use std::{cmp, fmt, mem, sync::Arc};
 
 use bytemuck::{AnyBitPattern, Pod, Zeroable};
 
 use crate::{
     buffer::{Buffer, BufferType},
-    config::Config,
+    config::{Config, ConfigAttribType},
     context::Context,
-    display::Display,
+    display::{Display, DisplayOwner},
     error::Error,
     raw::{Rectangle, VA_PADDING_LOW, VA_PADDING_MEDIUM},
-    surface::{RTFormat, Surface},
-    Entrypoint, Profile, Result, Rotation, SliceParameterBufferBase,
+    surface::{RTFormat, Surface, SurfaceAttribEnum},
+    Entrypoint, PixelFormat, Profile, Result, Rotation, SliceDataFlags, SliceParameterBufferBase,
 };
 
-use self::parser::{JpegParser, SegmentKind, SofMarker};
+use self::parser::{AdobeTransform, JpegError, JpegErrorKind, JpegParser, SegmentKind, SofMarker};
+
+pub use batch::{BatchDecodedJpeg, JpegBatchDecoder, Ticket};
+pub use decoder::{DecodedJpeg, JpegDecoder};
+pub use mjpeg::{MjpegDecoder, MjpegFrame};
+pub use rgba::{decode_to_rgba, JpegToRgbaPipeline, RgbaImage};
 
 ffi_enum! {
     pub enum ColorSpace: u8 {
@@ -101,6 +110,12 @@ impl PictureParameterBuffer {
         self.rotation = rotation;
     }
 
+    /// Sets the region of the image to decode.
+    #[inline]
+    pub fn set_crop_rectangle(&mut self, x: u16, y: u16, width: u16, height: u16) {
+        self.crop_rectangle = Rectangle::new(x as i16, y as i16, width, height);
+    }
+
     /// Adds a frame component.
     ///
     /// # Parameters
@@ -346,11 +361,156 @@ impl HuffmanTable {
     }
 }
 
+/// The `Li` and `Vij` values of a Huffman table.
+type HuffmanValues = ([u8; 16], Vec<u8>);
+
+/// Assigns the Huffman tables defined by a JPEG file to the 2 slots of a [`HuffmanTableBuffer`].
+///
+/// VA-API takes a single [`HuffmanTableBuffer`] per picture, but JPEG files with several scans
+/// may define tables between scans (libjpeg does this for non-interleaved files). A table that
+/// replaces one an earlier scan has already used is loaded into a free slot instead, and later
+/// scans are redirected to that slot.
+#[derive(Default)]
+struct HuffmanSlots {
+    /// Per table class (DC, AC) and destination (`Th`), the slot the table was loaded into.
+    destinations: [[Option<u8>; 2]; 2],
+    /// Per table class and slot, the `Li` and `Vij` values of the table loaded into the slot.
+    tables: [[Option<HuffmanValues>; 2]; 2],
+    /// Per table class and slot, whether a scan has referenced the slot.
+    used: [[bool; 2]; 2],
+}
+
+impl HuffmanSlots {
+    /// Defines the table of class `Tc` and destination `Th`.
+    ///
+    /// Returns `false` if the table replaces one that was used by an earlier scan, and there is no
+    /// free slot left to load it into.
+    #[allow(non_snake_case)]
+    fn define(&mut self, Tc: u8, Th: u8, Li: &[u8; 16], Vij: &[u8]) -> bool {
+        let class = usize::from(Tc);
+        let dest = usize::from(Th);
+        let current = self.destinations[class][dest];
+        if let Some(slot) = current {
+            if let Some((li, vij)) = &self.tables[class][usize::from(slot)] {
+                if li == Li && vij == Vij {
+                    return true;
+                }
+            }
+        }
+
+        // A slot is free if no scan has used it, and no other destination refers to it.
+        let is_free = |slot: u8| {
+            !self.used[class][usize::from(slot)]
+                && (0..2).all(|d| d == dest || self.destinations[class][d] != Some(slot))
+        };
+        let Some(slot) = current
+            .into_iter()
+            .chain([Th, 1 - Th])
+            .find(|&s| is_free(s))
+        else {
+            return false;
+        };
+        self.destinations[class][dest] = Some(slot);
+        self.tables[class][usize::from(slot)] = Some((*Li, Vij.to_vec()));
+        true
+    }
+
+    /// Returns the slot holding the table of class `Tc` and destination `Th`, and marks it as used.
+    #[allow(non_snake_case)]
+    fn resolve(&mut self, Tc: u8, Th: u8) -> u8 {
+        let slot = self.destinations[usize::from(Tc)][usize::from(Th)].unwrap_or(Th);
+        self.used[usize::from(Tc)][usize::from(slot)] = true;
+        slot
+    }
+
+    /// Returns the [`HuffmanTableBuffer`] containing all defined tables, or [`None`] if the file
+    /// did not define any.
+    fn buffer(&self) -> Option<HuffmanTableBuffer> {
+        let mut buf = HuffmanTableBuffer::zeroed();
+        let mut any = false;
+        for (class, tables) in self.tables.iter().enumerate() {
+            for (slot, table) in tables.iter().enumerate() {
+                let Some((li, vij)) = table else { continue };
+                let tbl = buf.huffman_table_mut(slot as u8);
+                match class {
+                    0 => tbl.set_dc_table(li, vij),
+                    _ => tbl.set_ac_table(li, vij),
+                }
+                any = true;
+            }
+        }
+        any.then_some(buf)
+    }
+}
+
+/// Describes whether a JPEG image can be decoded by VA-API, and if not, why.
+///
+/// Returned by [`JpegInfo::probe`]. Every variant except [`JpegSupport::Supported`] describes a
+/// valid JPEG image that uses a feature VA-API does not support, so the image has to be decoded
+/// in software instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum JpegSupport {
+    /// The image is a baseline JPEG that can be decoded by VA-API.
+    Supported,
+    /// The image uses progressive DCT coding (SOF2).
+    Progressive,
+    /// The image uses extended sequential DCT coding (SOF1), typically with 12-bit samples.
+    Extended {
+        /// Sample precision in bits.
+        precision: u8,
+    },
+    /// The image uses lossless coding (SOF3, SOF7, SOF11 or SOF15).
+    Lossless,
+    /// The image uses arithmetic instead of Huffman coding (SOF9 to SOF15).
+    ArithmeticCoded,
+    /// The image uses hierarchical (differential) coding (SOF5 to SOF7 or SOF13 to SOF15).
+    Hierarchical,
+    /// The image has 4 color components, typically CMYK or YCCK.
+    FourComponents,
+    /// The image has an unsupported number of color components (2, or more than 4).
+    UnsupportedComponentCount(u8),
+    /// The image has 3 color components, but uses a chroma subsampling scheme that VA-API does
+    /// not support.
+    UnsupportedSubsampling,
+}
+
+impl JpegSupport {
+    /// Returns whether the image can be decoded by VA-API.
+    #[inline]
+    pub fn is_supported(&self) -> bool {
+        *self == Self::Supported
+    }
+}
+
+impl fmt::Display for JpegSupport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Supported => f.write_str("supported"),
+            Self::Progressive => f.write_str("progressive JPEG"),
+            Self::Extended { precision } => {
+                write!(f, "extended sequential JPEG with {precision}-bit samples")
+            }
+            Self::Lossless => f.write_str("lossless JPEG"),
+            Self::ArithmeticCoded => f.write_str("arithmetic-coded JPEG"),
+            Self::Hierarchical => f.write_str("hierarchical JPEG"),
+            Self::FourComponents => f.write_str("JPEG with 4 color components"),
+            Self::UnsupportedComponentCount(n) => write!(f, "JPEG with {n} color components"),
+            Self::UnsupportedSubsampling => f.write_str("unsupported chroma subsampling"),
+        }
+    }
+}
+
 /// JPEG metadata required to create a VA-API JPEG decoding session.
 #[derive(Debug, Clone, Copy)]
 pub struct JpegInfo {
     width: u16,
     height: u16,
+    precision: u8,
+    num_components: u8,
+    sampling_factors: [(u8, u8); 4],
+    color_space: ColorSpace,
+    exif_orientation: Option<u8>,
 }
 
 impl JpegInfo {
@@ -361,42 +521,145 @@ impl JpegInfo {
     /// If this returns an error, the JPEG image is either malformed, or of an incompatible format
     /// that is not supported by VA-API. In that case, the caller should fall back to software
     /// decoding.
+    ///
+    /// Use [`JpegInfo::probe`] to distinguish between the two cases.
     pub fn new(jpeg: &[u8]) -> Result<Self> {
-        let mut parser = JpegParser::new(&jpeg);
-        let segment = parser
-            .next_segment()?
-            .ok_or_else(|| Error::from("missing SOI segment"))?;
-        if !matches!(segment.kind, parser::SegmentKind::Soi) {
-            return Err(Error::from("missing SOI segment"));
+        let (info, support) = Self::probe(jpeg)?;
+        if !support.is_supported() {
+            return Err(Error::from(format!(
+                "JPEG image is not supported by VA-API ({support})"
+            )));
+        }
+        Ok(info)
+    }
+
+    /// Parses the given JPEG image and determines whether VA-API can decode it.
+    ///
+    /// Unlike [`JpegInfo::new`], this does not fail when the image is valid but uses a feature
+    /// that VA-API does not support. Instead, the reason is reported as a [`JpegSupport`] value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JPEG image is malformed.
+    pub fn probe(jpeg: &[u8]) -> Result<(Self, JpegSupport)> {
+        let missing = |marker| JpegError::new_missing(jpeg.len(), marker);
+        let mut parser = JpegParser::new(jpeg);
+        let segment = parser.next_segment()?.ok_or_else(|| missing(0xD8))?;
+        if !matches!(segment.kind, SegmentKind::Soi) {
+            return Err(JpegError::new_missing(segment.pos, 0xD8).into());
         }
 
-        let sof = loop {
-            let segment = parser
-                .next_segment()?
-                .ok_or_else(|| Error::from("missing SOF segment"))?;
+        let mut adobe_transform = None;
+        let mut exif_orientation = None;
+        let (sof_pos, sof) = loop {
+            // Any SOF marker would do; report the absence as a missing baseline SOF0.
+            let segment = parser.next_segment()?.ok_or_else(|| missing(0xC0))?;
             match segment.kind {
-                SegmentKind::Sof(sof) => break sof,
+                SegmentKind::Sof(sof) => break (segment.pos, sof),
+                SegmentKind::Exif(exif) if exif_orientation.is_none() => {
+                    exif_orientation = exif.orientation();
+                }
+                SegmentKind::Adobe(adobe) => adobe_transform = Some(adobe.transform()),
                 _ => {}
             }
         };
 
-        if sof.sof() != SofMarker::SOF0 {
-            return Err(Error::from(format!(
-                "not a baseline JPEG ({:?})",
-                sof.sof()
-            )));
+        let marker = sof.sof();
+        let sof_error = |kind| Error::from(JpegError::in_segment(sof_pos, marker.0, kind));
+        let num_components = sof.components().len();
+        if num_components == 0 {
+            return Err(sof_error(JpegErrorKind::NoComponents));
         }
-        if sof.P() != 8 {
-            return Err(Error::from(format!(
-                "unsupported sample precision of {} bits (only 8-bit samples are supported)",
-                sof.P()
-            )));
+        let mut sampling_factors = [(0, 0); 4];
+        for (i, component) in sof.components().iter().enumerate() {
+            let (h, v) = (component.Hi(), component.Vi());
+            if !(1..=4).contains(&h) || !(1..=4).contains(&v) {
+                return Err(sof_error(JpegErrorKind::InvalidSamplingFactors {
+                    component: component.Ci(),
+                    h,
+                    v,
+                }));
+            }
+            if let Some(factors) = sampling_factors.get_mut(i) {
+                *factors = (h, v);
+            }
         }
 
-        Ok(Self {
+        if marker == SofMarker::SOF0 && sof.P() != 8 {
+            return Err(sof_error(JpegErrorKind::InvalidPrecision(sof.P())));
+        }
+
+        // RGB JPEGs are identified either by an Adobe segment specifying no color transform, or by
+        // using the ASCII characters `R`, `G`, `B` as the component IDs.
+        let ids = sof.components().iter().map(|c| c.Ci()).collect::<Vec<_>>();
+        let color_space = if num_components != 3
+            || adobe_transform.is_some_and(|t| t != AdobeTransform::Unknown)
+        {
+            ColorSpace::YUV
+        } else if ids == b"BGR" {
+            ColorSpace::BGR
+        } else if ids == b"RGB" || adobe_transform == Some(AdobeTransform::Unknown) {
+            ColorSpace::RGB
+        } else {
+            ColorSpace::YUV
+        };
+
+        let info = Self {
             width: sof.X(),
             height: sof.Y(),
-        })
+            precision: sof.P(),
+            num_components: num_components as u8,
+            sampling_factors,
+            color_space,
+            exif_orientation,
+        };
+
+        let support = match marker {
+            SofMarker::SOF3 | SofMarker::SOF7 | SofMarker::SOF11 | SofMarker::SOF15 => {
+                JpegSupport::Lossless
+            }
+            SofMarker::SOF9 | SofMarker::SOF10 | SofMarker::SOF13 | SofMarker::SOF14 => {
+                JpegSupport::ArithmeticCoded
+            }
+            SofMarker::SOF5 | SofMarker::SOF6 => JpegSupport::Hierarchical,
+            SofMarker::SOF2 => JpegSupport::Progressive,
+            SofMarker::SOF1 => JpegSupport::Extended { precision: sof.P() },
+            _ => match num_components {
+                1 => JpegSupport::Supported,
+                3 if info.formats().is_some() => JpegSupport::Supported,
+                3 => JpegSupport::UnsupportedSubsampling,
+                4 => JpegSupport::FourComponents,
+                n => JpegSupport::UnsupportedComponentCount(n as u8),
+            },
+        };
+
+        Ok((info, support))
+    }
+
+    /// Returns the [`RTFormat`] and [`PixelFormat`] of a [`Surface`] that can hold the decoded
+    /// image.
+    ///
+    /// For 3-component images, both chroma components must use a sampling factor of 1x1, while
+    /// the luma component may use 1x1 (4:4:4), 2x1 (4:2:2), 1x2 (4:4:0), 2x2 (4:2:0) or 4x1
+    /// (4:1:1). RGB images must not be subsampled.
+    fn formats(&self) -> Option<(RTFormat, PixelFormat)> {
+        let [luma, cb, cr, _] = self.sampling_factors;
+        match self.num_components {
+            1 => Some((RTFormat::YUV400, PixelFormat::Y800)),
+            3 if cb != (1, 1) || cr != (1, 1) => None,
+            3 if self.color_space != ColorSpace::YUV => {
+                (luma == (1, 1)).then_some((RTFormat::RGBP, PixelFormat::RGBP))
+            }
+            3 => Some(match luma {
+                (1, 1) => (RTFormat::YUV444, PixelFormat::YUV444P),
+                (2, 1) => (RTFormat::YUV422, PixelFormat::YUV422H),
+                (1, 2) => (RTFormat::YUV422, PixelFormat::YUV422V),
+                (2, 2) => (RTFormat::YUV420, PixelFormat::NV12),
+                (4, 1) => (RTFormat::YUV411, PixelFormat::YUV411P),
+                _ => return None,
+            }),
+            _ => None,
+        }
     }
 
     #[inline]
@@ -408,6 +671,199 @@ impl JpegInfo {
     pub fn height(&self) -> u16 {
         self.height
     }
+
+    /// Returns the sample precision in bits.
+    #[inline]
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Returns the number of color components in the image.
+    #[inline]
+    pub fn num_components(&self) -> u8 {
+        self.num_components
+    }
+
+    /// Returns the horizontal and vertical sampling factors of the image's color components.
+    ///
+    /// Only the first 4 components are included.
+    #[inline]
+    pub fn sampling_factors(&self) -> &[(u8, u8)] {
+        let len = cmp::min(
+            usize::from(self.num_components),
+            self.sampling_factors.len(),
+        );
+        &self.sampling_factors[..len]
+    }
+
+    /// Returns the color space of the encoded image data.
+    ///
+    /// This is [`ColorSpace::RGB`] or [`ColorSpace::BGR`] for images that store RGB data directly
+    /// (indicated by an Adobe APP14 segment or by the component IDs), and [`ColorSpace::YUV`]
+    /// otherwise.
+    #[inline]
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// Returns the value of the EXIF orientation tag, if the image contains one.
+    ///
+    /// The value ranges from 1 to 8, with 1 meaning that the image is stored upright. See
+    /// [`DecodeOptions::with_exif_orientation`] for applying it during decoding.
+    #[inline]
+    pub fn exif_orientation(&self) -> Option<u8> {
+        self.exif_orientation
+    }
+
+    /// Returns the [`RTFormat`] of a [`Surface`] that the image can be decoded to.
+    ///
+    /// Returns [`None`] if the image's component layout is not supported by VA-API.
+    #[inline]
+    pub fn rt_format(&self) -> Option<RTFormat> {
+        self.formats().map(|(rt, _)| rt)
+    }
+
+    /// Returns the [`PixelFormat`] of a [`Surface`] that the image can be decoded to.
+    ///
+    /// Returns [`None`] if the image's component layout is not supported by VA-API.
+    #[inline]
+    pub fn pixel_format(&self) -> Option<PixelFormat> {
+        self.formats().map(|(_, pf)| pf)
+    }
+}
+
+/// Options for decoding a JPEG image.
+///
+/// The default options decode the whole image without rotation.
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeOptions {
+    rotation: Rotation,
+    crop: Option<(u16, u16, u16, u16)>,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecodeOptions {
+    pub fn new() -> Self {
+        Self {
+            rotation: Rotation::NONE,
+            crop: None,
+        }
+    }
+
+    /// Rotates the image clockwise while decoding.
+    ///
+    /// Not all implementations support rotation; the supported angles are reported by the
+    /// [`ConfigAttribType::DecJPEG`] attribute, and decoding will fail if the requested rotation is
+    /// not supported.
+    ///
+    /// [`ConfigAttribType::DecJPEG`]: crate::config::ConfigAttribType::DecJPEG
+    #[inline]
+    pub fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Rotates the image so that it comes out upright, according to its EXIF orientation tag.
+    ///
+    /// The JPEG decoder can only rotate, not mirror the image. For images with a mirrored EXIF
+    /// orientation (2, 4, 5 or 7), and for images without one, the rotation is left unchanged.
+    pub fn with_exif_orientation(self, info: &JpegInfo) -> Self {
+        match info.exif_orientation() {
+            Some(1) => self.with_rotation(Rotation::NONE),
+            Some(3) => self.with_rotation(Rotation::R180),
+            Some(6) => self.with_rotation(Rotation::R90),
+            Some(8) => self.with_rotation(Rotation::R270),
+            Some(orientation) => {
+                log::debug!("cannot apply mirrored EXIF orientation {orientation}");
+                self
+            }
+            None => self,
+        }
+    }
+
+    /// Only decodes the given rectangle of the image.
+    ///
+    /// The coordinates refer to the unrotated image. The decoded [`Surface`] will have the size of
+    /// the crop rectangle (after rotation).
+    ///
+    /// Not all implementations support cropping (see [`JpegDecodeSession::supports_crop`]), and
+    /// decoding will fail if it is not supported.
+    #[inline]
+    pub fn with_crop(mut self, x: u16, y: u16, width: u16, height: u16) -> Self {
+        self.crop = Some((x, y, width, height));
+        self
+    }
+
+    #[inline]
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    /// Returns the crop rectangle as `(x, y, width, height)`, if one is set.
+    #[inline]
+    pub fn crop(&self) -> Option<(u16, u16, u16, u16)> {
+        self.crop
+    }
+
+    /// Validates the options for `info`, and returns the size and formats of the output
+    /// [`Surface`].
+    ///
+    /// `features` is the value of the `DecJPEG` config attribute.
+    fn resolve(
+        &self,
+        info: &JpegInfo,
+        (rt_format, pixel_format): (RTFormat, PixelFormat),
+        features: u32,
+    ) -> Result<((u32, u32), (RTFormat, PixelFormat))> {
+        if self.rotation != Rotation::NONE && features & (1 << self.rotation.0) == 0 {
+            return Err(Error::from(format!(
+                "JPEG decode rotation {:?} is not supported by the implementation",
+                self.rotation
+            )));
+        }
+        if self.crop.is_some() && features & DEC_JPEG_CROP == 0 {
+            return Err(Error::from(
+                "JPEG decode cropping is not supported by the implementation",
+            ));
+        }
+
+        let (width, height) = match self.crop {
+            Some((x, y, width, height)) => {
+                if width == 0
+                    || height == 0
+                    || u32::from(x) + u32::from(width) > u32::from(info.width())
+                    || u32::from(y) + u32::from(height) > u32::from(info.height())
+                    || x > i16::MAX as u16
+                    || y > i16::MAX as u16
+                {
+                    return Err(Error::from(format!(
+                        "crop rectangle {width}x{height} at ({x},{y}) exceeds the {}x{} image",
+                        info.width(),
+                        info.height()
+                    )));
+                }
+                (u32::from(width), u32::from(height))
+            }
+            None => (u32::from(info.width()), u32::from(info.height())),
+        };
+
+        if self.rotation == Rotation::R90 || self.rotation == Rotation::R270 {
+            // Rotating by 90° turns horizontal chroma subsampling into vertical subsampling.
+            let pixel_format = match pixel_format {
+                PixelFormat::YUV422H => PixelFormat::YUV422V,
+                PixelFormat::YUV422V => PixelFormat::YUV422H,
+                other => other,
+            };
+            Ok(((height, width), (rt_format, pixel_format)))
+        } else {
+            Ok(((width, height), (rt_format, pixel_format)))
+        }
+    }
 }
 
 /// A VA-API JPEG decoding session.
@@ -419,6 +875,10 @@ impl JpegInfo {
 pub struct JpegDecodeSession {
     width: u32,
     height: u32,
+    formats: (RTFormat, PixelFormat),
+    surface_size: (u32, u32),
+    features: u32,
+    max_slice_data_size: Option<usize>,
     jpeg_surface: Surface,
     jpeg_context: Context,
 }
@@ -434,21 +894,83 @@ impl JpegDecodeSession {
     ///
     /// [`Surface`]: crate::surface::Surface
     pub fn new(display: &Display, width: u16, height: u16) -> Result<Self> {
+        Self::with_formats(
+            display,
+            width,
+            height,
+            (RTFormat::YUV420, PixelFormat::NV12),
+        )
+    }
+
+    /// Creates [`Surface`]s and [`Context`]s to decode JPEG images like the one described by
+    /// `info`.
+    ///
+    /// Unlike [`JpegDecodeSession::new`], which assumes 4:2:0 chroma subsampling, this creates a
+    /// [`Surface`] matching the image's subsampling and color space right away.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the image's component layout is not supported, or
+    /// if VA-API object creation fails.
+    ///
+    /// [`Surface`]: crate::surface::Surface
+    pub fn with_info(display: &Display, info: &JpegInfo) -> Result<Self> {
+        let formats = info
+            .formats()
+            .ok_or_else(|| Error::from("JPEG component layout is not supported by VA-API"))?;
+        Self::with_formats(display, info.width(), info.height(), formats)
+    }
+
+    fn with_formats(
+        display: &Display,
+        width: u16,
+        height: u16,
+        formats: (RTFormat, PixelFormat),
+    ) -> Result<Self> {
         let width = u32::from(width);
         let height = u32::from(height);
 
-        let config = Config::new(&display, Profile::JPEGBaseline, Entrypoint::VLD)?;
+        let config = Config::new(display, Profile::JPEGBaseline, Entrypoint::VLD)?;
         let jpeg_context = Context::new(&config, width, height)?;
-        let jpeg_surface = Surface::new(&display, width, height, RTFormat::YUV420)?;
+        let jpeg_surface = create_surface(&display.d, width, height, formats)?;
 
         Ok(Self {
             width,
             height,
+            formats,
+            surface_size: (width, height),
+            features: query_dec_jpeg_features(display)?,
+            max_slice_data_size: None,
             jpeg_surface,
             jpeg_context,
         })
     }
 
+    /// Sets the maximum amount of scan data to submit to the driver in one piece.
+    ///
+    /// Some drivers limit the size of slice data buffers. When this is set, scans that exceed
+    /// `size` bytes are split at their restart markers, and submitted in several parts using
+    /// [`SliceDataFlags`]. Scans without restart markers cannot be split and are always submitted
+    /// as a whole.
+    ///
+    /// By default, there is no limit.
+    #[inline]
+    pub fn set_max_slice_data_size(&mut self, size: Option<usize>) {
+        self.max_slice_data_size = size;
+    }
+
+    /// Returns whether the implementation supports decoding a part of the image, as requested by
+    /// [`DecodeOptions::with_crop`].
+    #[inline]
+    pub fn supports_crop(&self) -> bool {
+        self.features & DEC_JPEG_CROP != 0
+    }
+
+    /// Returns the [`Surface`] that JPEG images are decoded to.
+    ///
+    /// Note that [`JpegDecodeSession::decode`] will replace this [`Surface`] with a new one when
+    /// the chroma subsampling or color space of the decoded image differs from the previous one,
+    /// or when the [`DecodeOptions`] require a differently sized [`Surface`].
     #[inline]
     pub fn surface(&mut self) -> &mut Surface {
         &mut self.jpeg_surface
@@ -463,6 +985,118 @@ impl JpegDecodeSession {
     /// This method returns an error when the JPEG is malformed or VA-API returns an error during
     /// decoding.
     pub fn decode(&mut self, jpeg: &[u8]) -> Result<&mut Surface> {
+        self.decode_with_options(jpeg, &DecodeOptions::new())
+    }
+
+    /// Decodes a baseline JPEG with the given [`DecodeOptions`], returning a [`Surface`] containing
+    /// the decoded image.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the JPEG is malformed, the options are invalid or not
+    /// supported by the implementation, or VA-API returns an error during decoding.
+    pub fn decode_with_options(
+        &mut self,
+        jpeg: &[u8],
+        options: &DecodeOptions,
+    ) -> Result<&mut Surface> {
+        let (info, formats) = probe_supported(jpeg)?;
+        if u32::from(info.height()) != self.height || u32::from(info.width()) != self.width {
+            return Err(Error::from(format!(
+                "image dimension {}x{} does not match context dimension {}x{}",
+                info.width(),
+                info.height(),
+                self.width,
+                self.height
+            )));
+        }
+        let (surface_size, formats) = options.resolve(&info, formats, self.features)?;
+        if formats != self.formats || surface_size != self.surface_size {
+            log::debug!(
+                "JPEG output changed from {:?} {:?} to {:?} {:?}, recreating surface",
+                self.surface_size,
+                self.formats,
+                surface_size,
+                formats,
+            );
+            let (width, height) = surface_size;
+            self.jpeg_surface = create_surface(&self.jpeg_context.d, width, height, formats)?;
+            self.formats = formats;
+            self.surface_size = surface_size;
+        }
+
+        decode_picture(
+            &mut self.jpeg_context,
+            &mut self.jpeg_surface,
+            jpeg,
+            info.color_space(),
+            options,
+            self.max_slice_data_size,
+        )?;
+
+        Ok(&mut self.jpeg_surface)
+    }
+}
+
+/// Bit of the `DecJPEG` config attribute that indicates support for [`DecodeOptions::with_crop`].
+const DEC_JPEG_CROP: u32 = 1 << 4;
+
+/// Queries the `DecJPEG` config attribute.
+///
+/// The lowest 4 bits contain the supported rotations, as a bitmask of `1 << Rotation`, and bit 4
+/// ([`DEC_JPEG_CROP`]) indicates whether cropping is supported. The remaining bits are reserved
+/// and masked out.
+fn query_dec_jpeg_features(display: &Display) -> Result<u32> {
+    let value = display.query_config_attribute(
+        Profile::JPEGBaseline,
+        Entrypoint::VLD,
+        ConfigAttribType::DecJPEG,
+    )?;
+    Ok(value.map_or(0, |value| value & 0x1f))
+}
+
+/// Probes `jpeg` and returns its [`JpegInfo`] and the formats of a [`Surface`] to decode it to.
+///
+/// Returns an error if the image is malformed or not supported by VA-API.
+fn probe_supported(jpeg: &[u8]) -> Result<(JpegInfo, (RTFormat, PixelFormat))> {
+    let (info, support) = JpegInfo::probe(jpeg)?;
+    if !support.is_supported() {
+        return Err(Error::from(format!(
+            "JPEG image is not supported by VA-API ({support})"
+        )));
+    }
+    let formats = info
+        .formats()
+        .ok_or_else(|| Error::from("JPEG component layout is not supported by VA-API"))?;
+    Ok((info, formats))
+}
+
+/// The parameters and slices of a JPEG picture, ready to be submitted to VA-API.
+///
+/// This contains all the header and scan validation needed for decoding, but does not interact
+/// with the driver, so that it can be tested on any malformed input.
+struct PictureParams<'a> {
+    huffman: HuffmanTableBuffer,
+    iq: IQMatrixBuffer,
+    picture: PictureParameterBuffer,
+    /// The slice parameters and data of each slice.
+    slices: Vec<(SliceParameterBuffer, &'a [u8])>,
+}
+
+impl<'a> PictureParams<'a> {
+    /// Parses `jpeg` and builds the parameters to decode it with.
+    ///
+    /// `color_space` is the image's [`JpegInfo::color_space`]. Scans that exceed
+    /// `max_slice_data_size` are split into several slices.
+    ///
+    /// Images without a DHT segment are decoded using the example Huffman tables from Annex K of
+    /// the JPEG specification. Motion-JPEG streams usually omit the DHT segment and rely on this.
+    fn parse(
+        jpeg: &'a [u8],
+        color_space: ColorSpace,
+        options: &DecodeOptions,
+        max_slice_data_size: Option<usize>,
+    ) -> Result<Self> {
         // TODO make this more flexible and move to `error` module
         macro_rules! bail {
             ($($args:tt)*) => {
@@ -472,15 +1106,18 @@ impl JpegDecodeSession {
             };
         }
 
-        let mut dhtbuf = HuffmanTableBuffer::zeroed();
+        let mut huffman_slots = HuffmanSlots::default();
+        let mut width = 0;
+        let mut height = 0;
         let mut max_h_factor = 0;
         let mut max_v_factor = 0;
+        let mut frame_components = Vec::new();
         let mut restart_interval = 0;
         let mut ppbuf = None;
-        let mut slice = None;
+        let mut scans = Vec::new();
         let mut iqbuf = IQMatrixBuffer::new();
 
-        let mut parser = JpegParser::new(&jpeg);
+        let mut parser = JpegParser::new(jpeg);
         while let Some(segment) = parser.next_segment()? {
             match segment.kind {
                 SegmentKind::Dqt(dqt) => {
@@ -488,6 +1125,9 @@ impl JpegDecodeSession {
                         if dqt.Pq() != 0 {
                             bail!("unexpected value `{}` for DQT Pq", dqt.Pq());
                         }
+                        if dqt.Tq() > 3 {
+                            bail!("invalid DQT destination slot {} (expected 0-3)", dqt.Tq());
+                        }
                         iqbuf.set_quantization_table(dqt.Tq(), &dqt.Qk());
                     }
                 }
@@ -499,36 +1139,49 @@ impl JpegDecodeSession {
                                 table.Th()
                             );
                         }
-                        let tbl = dhtbuf.huffman_table_mut(table.Th());
-                        match table.Tc() {
-                            0 => tbl.set_dc_table(table.Li(), table.Vij()),
-                            1 => tbl.set_ac_table(table.Li(), table.Vij()),
+                        let max_values = match table.Tc() {
+                            0 => 12,
+                            1 => 162,
                             _ => bail!("invalid DHT class {}", table.Tc()),
+                        };
+                        if table.Vij().len() > max_values {
+                            bail!(
+                                "Huffman table defines {} values (expected at most {max_values})",
+                                table.Vij().len()
+                            );
+                        }
+                        if !huffman_slots.define(table.Tc(), table.Th(), table.Li(), table.Vij()) {
+                            // VA-API only takes one set of Huffman tables per picture.
+                            bail!(
+                                "too many different Huffman tables of class {} (at most 2 are supported)",
+                                table.Tc()
+                            );
                         }
                     }
                 }
                 SegmentKind::Dri(dri) => restart_interval = dri.Ri(),
                 SegmentKind::Sof(sof) => {
-                    if sof.sof() != SofMarker::SOF0 {
-                        bail!("not a baseline JPEG (SOF={:?})", sof.sof());
+                    if ppbuf.is_some() {
+                        bail!("file contains more than one SOF segment");
                     }
-
-                    if sof.P() != 8 {
-                        bail!("sample precision of {} bits is not supported", sof.P());
+                    if sof.components().is_empty() {
+                        bail!("SOF segment has no color components");
                     }
-
-                    if u32::from(sof.Y()) != self.height || u32::from(sof.X()) != self.width {
-                        bail!(
-                            "image dimension {}x{} does not match context dimention {}x{}",
-                            sof.X(),
-                            sof.Y(),
-                            self.width,
-                            self.height
-                        );
+                    width = u32::from(sof.X());
+                    height = u32::from(sof.Y());
+                    let mut buf = PictureParameterBuffer::new(sof.X(), sof.Y(), color_space);
+                    buf.set_rotation(options.rotation());
+                    if let Some((x, y, width, height)) = options.crop() {
+                        buf.set_crop_rectangle(x, y, width, height);
                     }
-
-                    let mut buf = PictureParameterBuffer::new(sof.X(), sof.Y(), ColorSpace::YUV);
                     for component in sof.components() {
+                        let (h, v) = (component.Hi(), component.Vi());
+                        if !(1..=4).contains(&h) || !(1..=4).contains(&v) {
+                            bail!(
+                                "invalid sampling factors {h}x{v} for component {}",
+                                component.Ci()
+                            );
+                        }
                         buf.push_component(
                             component.Ci(),
                             component.Hi(),
@@ -537,6 +1190,7 @@ impl JpegDecodeSession {
                         );
                         max_h_factor = cmp::max(u32::from(component.Hi()), max_h_factor);
                         max_v_factor = cmp::max(u32::from(component.Vi()), max_v_factor);
+                        frame_components.push(*component);
                     }
                     ppbuf = Some(buf);
                 }
@@ -555,22 +1209,50 @@ impl JpegDecodeSession {
                         bail!("invalid SOS header: Ah={}, Al={}", sos.Ah(), sos.Al());
                     }
 
-                    let slice_data = sos.data();
-                    let num_mcus = ((self.width + max_h_factor * 8 - 1) / (max_h_factor * 8))
-                        * ((self.height + max_v_factor * 8 - 1) / (max_v_factor * 8));
+                    if ppbuf.is_none() {
+                        bail!("SOS segment precedes SOF segment");
+                    }
+                    if sos.components().is_empty() || sos.components().len() > 4 {
+                        bail!(
+                            "invalid number of scan components: {}",
+                            sos.components().len()
+                        );
+                    }
+
+                    let num_mcus = if let [component] = sos.components() {
+                        // Non-interleaved scans consist of the 8x8 blocks of a single component.
+                        let Some(frame_component) =
+                            frame_components.iter().find(|c| c.Ci() == component.Csj())
+                        else {
+                            bail!("scan references unknown component {}", component.Csj());
+                        };
+                        let width =
+                            (width * u32::from(frame_component.Hi())).div_ceil(max_h_factor);
+                        let height =
+                            (height * u32::from(frame_component.Vi())).div_ceil(max_v_factor);
+                        width.div_ceil(8) * height.div_ceil(8)
+                    } else {
+                        width.div_ceil(max_h_factor * 8) * height.div_ceil(max_v_factor * 8)
+                    };
                     let mut slice_params = SliceParameterBuffer::new(
-                        SliceParameterBufferBase::new(slice_data.len().try_into().unwrap()),
+                        SliceParameterBufferBase::new(0),
                         restart_interval,
                         num_mcus,
                     );
                     for component in sos.components() {
-                        slice_params.push_component(
-                            component.Csj(),
-                            component.Tdj(),
-                            component.Taj(),
-                        );
+                        if component.Tdj() > 1 || component.Taj() > 1 {
+                            bail!(
+                                "invalid Huffman table selector {}/{} for scan component {} (expected 0 or 1)",
+                                component.Tdj(),
+                                component.Taj(),
+                                component.Csj(),
+                            );
+                        }
+                        let dc_slot = huffman_slots.resolve(0, component.Tdj());
+                        let ac_slot = huffman_slots.resolve(1, component.Taj());
+                        slice_params.push_component(component.Csj(), dc_slot, ac_slot);
                     }
-                    slice = Some((slice_params, slice_data));
+                    scans.push((slice_params, sos.data()));
                 }
                 SegmentKind::Eoi => break,
                 _ => {}
@@ -580,29 +1262,155 @@ impl JpegDecodeSession {
         let Some(ppbuf) = ppbuf else {
             bail!("file is missing SOI segment")
         };
-        let Some((slice_params, slice_data)) = slice else {
-            bail!("file is missing SOS header")
-        };
+        if scans.is_empty() {
+            bail!("file is missing SOS header");
+        }
+
+        let huffman = huffman_slots
+            .buffer()
+            .unwrap_or_else(HuffmanTableBuffer::default_tables);
+
+        // Every scan is submitted as its own slice. Scans that exceed the maximum slice data size
+        // are submitted in several parts, split at restart markers.
+        let mut slices = Vec::new();
+        for (slice_params, slice_data) in scans {
+            let parts = split_scan_data(slice_data, max_slice_data_size);
+            let num_parts = parts.len();
+            for (i, part) in parts.into_iter().enumerate() {
+                let flags = match (i, num_parts) {
+                    (_, 1) => SliceDataFlags::ALL,
+                    (0, _) => SliceDataFlags::BEGIN,
+                    (i, n) if i == n - 1 => SliceDataFlags::END,
+                    _ => SliceDataFlags::MIDDLE,
+                };
+                let mut params = slice_params;
+                params.base =
+                    SliceParameterBufferBase::new(part.len().try_into().map_err(|_| {
+                        Error::from(format!("slice data size {} exceeds limit", part.len()))
+                    })?);
+                params.base.set_slice_data_flags(flags);
+
+                slices.push((params, part));
+            }
+        }
 
-        let mut buf_dht = Buffer::new_param(&self.jpeg_context, BufferType::HuffmanTable, dhtbuf)?;
-        let mut buf_iq = Buffer::new_param(&self.jpeg_context, BufferType::IQMatrix, iqbuf)?;
-        let mut buf_pp =
-            Buffer::new_param(&self.jpeg_context, BufferType::PictureParameter, ppbuf)?;
-        let mut buf_slice_param =
-            Buffer::new_param(&self.jpeg_context, BufferType::SliceParameter, slice_params)?;
-        let mut buf_slice_data =
-            Buffer::new_data(&self.jpeg_context, BufferType::SliceData, &slice_data)?;
+        Ok(Self {
+            huffman,
+            iq: iqbuf,
+            picture: ppbuf,
+            slices,
+        })
+    }
+}
 
-        let mut picture = self.jpeg_context.begin_picture(&mut self.jpeg_surface)?;
-        unsafe {
-            picture.render_picture(&mut buf_dht)?;
-            picture.render_picture(&mut buf_iq)?;
-            picture.render_picture(&mut buf_pp)?;
-            picture.render_picture(&mut buf_slice_param)?;
-            picture.render_picture(&mut buf_slice_data)?;
-            picture.end_picture()?;
+/// Submits the decode operation for `jpeg` to `context`, decoding to `surface`.
+///
+/// `surface` has to be at least as large as the image, and use a compatible format (see
+/// [`probe_supported`] and [`DecodeOptions::resolve`]). See [`PictureParams::parse`] for the
+/// remaining parameters.
+fn decode_picture(
+    context: &mut Context,
+    surface: &mut Surface,
+    jpeg: &[u8],
+    color_space: ColorSpace,
+    options: &DecodeOptions,
+    max_slice_data_size: Option<usize>,
+) -> Result<()> {
+    let params = PictureParams::parse(jpeg, color_space, options, max_slice_data_size)?;
+
+    let mut buf_dht = Buffer::new_param(context, BufferType::HuffmanTable, params.huffman)?;
+    let mut buf_iq = Buffer::new_param(context, BufferType::IQMatrix, params.iq)?;
+    let mut buf_pp = Buffer::new_param(context, BufferType::PictureParameter, params.picture)?;
+    let mut slice_bufs = Vec::new();
+    for (slice_params, slice_data) in params.slices {
+        slice_bufs.push((
+            Buffer::new_param(context, BufferType::SliceParameter, slice_params)?,
+            Buffer::new_data(context, BufferType::SliceData, slice_data)?,
+        ));
+    }
+
+    let mut picture = context.begin_picture(surface)?;
+    unsafe {
+        picture.render_picture(&mut buf_dht)?;
+        picture.render_picture(&mut buf_iq)?;
+        picture.render_picture(&mut buf_pp)?;
+        for (buf_slice_param, buf_slice_data) in &mut slice_bufs {
+            picture.render_picture(buf_slice_param)?;
+            picture.render_picture(buf_slice_data)?;
         }
+        picture.end_picture()?;
+    }
 
-        Ok(&mut self.jpeg_surface)
+    Ok(())
+}
+
+/// Creates a [`Surface`] with the given formats, falling back to letting the driver pick the
+/// pixel format if it rejects the requested one.
+fn create_surface(
+    d: &Arc<DisplayOwner>,
+    width: u32,
+    height: u32,
+    (rt_format, pixel_format): (RTFormat, PixelFormat),
+) -> Result<Surface> {
+    let mut attribs = [SurfaceAttribEnum::PixelFormat(pixel_format).into()];
+    match Surface::create(d, width, height, rt_format, &mut attribs) {
+        Ok(surface) => Ok(surface),
+        Err(e) => {
+            log::debug!(
+                "failed to create {pixel_format} surface ({e}), letting driver pick format"
+            );
+            Surface::create(d, width, height, rt_format, &mut [])
+        }
+    }
+}
+
+/// Returns the offsets in `data` right after each `RST` marker.
+///
+/// Since the entropy coder is reset at every `RST` marker, these are the positions at which scan
+/// data can be split.
+fn restart_boundaries(data: &[u8]) -> Vec<usize> {
+    let mut boundaries = Vec::new();
+    let mut i = 0;
+    while i + 1 < data.len() {
+        if data[i] != 0xff {
+            i += 1;
+            continue;
+        }
+
+        // Skip fill bytes.
+        let mut j = i + 1;
+        while j < data.len() && data[j] == 0xff {
+            j += 1;
+        }
+        if let Some(0xD0..=0xD7) = data.get(j) {
+            boundaries.push(j + 1);
+        }
+        i = j + 1;
+    }
+    boundaries
+}
+
+/// Splits scan data into parts of at most `max_size` bytes, at restart marker boundaries.
+///
+/// Parts are only split at restart markers, so if a single restart interval exceeds `max_size`,
+/// the resulting part will be larger than `max_size`.
+fn split_scan_data(data: &[u8], max_size: Option<usize>) -> Vec<&[u8]> {
+    let Some(max_size) = max_size else {
+        return vec![data];
+    };
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut last = 0;
+    for boundary in restart_boundaries(data).into_iter().chain([data.len()]) {
+        if boundary - start > max_size && last > start {
+            parts.push(&data[start..last]);
+            start = last;
+        }
+        last = boundary;
+    }
+    if start < data.len() || parts.is_empty() {
+        parts.push(&data[start..]);
     }
+    parts
 }
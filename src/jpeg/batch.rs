@@ -0,0 +1,392 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+    config::Config,
+    context::Context,
+    display::Display,
+    surface::{RTFormat, Surface},
+    Entrypoint, Error, PixelFormat, Profile, Result,
+};
+
+use super::{create_surface, decode_picture, probe_supported, DecodeOptions, JpegInfo};
+
+/// Image dimensions are rounded up to a multiple of this, so that similarly sized images can share
+/// a [`Surface`].
+const SIZE_GRANULARITY: u32 = 256;
+
+/// How long [`JpegBatchDecoder::next_completed`] waits for the oldest in-flight image before
+/// checking whether any of the others has finished first.
+const SYNC_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Identifies an image submitted to a [`JpegBatchDecoder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ticket(u64);
+
+/// Decodes many JPEG images, keeping several of them in flight on the GPU at once.
+///
+/// Images are submitted with [`JpegBatchDecoder::submit`], which returns a [`Ticket`] right after
+/// the decode operation has been queued. The decoded image can then be retrieved with
+/// [`JpegBatchDecoder::poll`] or [`JpegBatchDecoder::wait`], or all images can be retrieved in
+/// the order in which they finish decoding with [`JpegBatchDecoder::next_completed`].
+///
+/// At most `max_in_flight` images are decoded concurrently. When that limit is reached,
+/// [`JpegBatchDecoder::submit`] waits for the oldest pending image to finish first. Finished images
+/// are kept until they are retrieved, so callers should retrieve images regularly to bound memory
+/// usage.
+pub struct JpegBatchDecoder {
+    config: Config,
+    max_in_flight: usize,
+    max_slice_data_size: Option<usize>,
+    /// The decode context and its (rounded-up) size. Grown as needed to fit the largest image.
+    context: Option<((u32, u32), Context)>,
+    /// Surfaces that are not in use, available for later images.
+    free_surfaces: Vec<PooledSurface>,
+    in_flight: VecDeque<Pending>,
+    completed: VecDeque<(Ticket, Result<BatchDecodedJpeg>)>,
+    next_ticket: u64,
+}
+
+struct PooledSurface {
+    /// Rounded-up surface size.
+    size: (u32, u32),
+    formats: (RTFormat, PixelFormat),
+    surface: Surface,
+}
+
+struct Pending {
+    ticket: Ticket,
+    info: JpegInfo,
+    surface: PooledSurface,
+}
+
+impl Pending {
+    fn finish(self) -> BatchDecodedJpeg {
+        BatchDecodedJpeg {
+            ticket: self.ticket,
+            info: self.info,
+            surface: self.surface,
+        }
+    }
+}
+
+impl JpegBatchDecoder {
+    /// Creates a new [`JpegBatchDecoder`] that decodes up to `max_in_flight` images at once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `max_in_flight` is 0, or if the implementation does not support JPEG
+    /// decoding.
+    pub fn new(display: &Display, max_in_flight: usize) -> Result<Self> {
+        if max_in_flight == 0 {
+            return Err(Error::from(
+                "maximum number of in-flight images must not be 0",
+            ));
+        }
+
+        Ok(Self {
+            config: Config::new(display, Profile::JPEGBaseline, Entrypoint::VLD)?,
+            max_in_flight,
+            max_slice_data_size: None,
+            context: None,
+            free_surfaces: Vec::new(),
+            in_flight: VecDeque::new(),
+            completed: VecDeque::new(),
+            next_ticket: 0,
+        })
+    }
+
+    /// Sets the maximum amount of scan data to submit to the driver in one piece.
+    ///
+    /// See [`JpegDecodeSession::set_max_slice_data_size`] for details.
+    ///
+    /// [`JpegDecodeSession::set_max_slice_data_size`]:
+    /// super::JpegDecodeSession::set_max_slice_data_size
+    #[inline]
+    pub fn set_max_slice_data_size(&mut self, size: Option<usize>) {
+        self.max_slice_data_size = size;
+    }
+
+    /// Returns the number of submitted images that have not been retrieved yet.
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.in_flight.len() + self.completed.len()
+    }
+
+    /// Submits a baseline JPEG image for decoding.
+    ///
+    /// If `max_in_flight` images are already being decoded, this waits for the oldest one to
+    /// finish first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JPEG is malformed or unsupported, or VA-API returns an error while
+    /// submitting the decode operation. No [`Ticket`] is issued in that case.
+    pub fn submit(&mut self, jpeg: &[u8]) -> Result<Ticket> {
+        let (info, formats) = probe_supported(jpeg)?;
+        if info.width() == 0 || info.height() == 0 {
+            return Err(Error::from(format!(
+                "invalid image dimensions {}x{}",
+                info.width(),
+                info.height()
+            )));
+        }
+
+        while self.in_flight.len() >= self.max_in_flight {
+            self.retire_oldest();
+        }
+
+        let round = |size: u16| u32::from(size).next_multiple_of(SIZE_GRANULARITY);
+        let size = (round(info.width()), round(info.height()));
+
+        let fits = |(w, h): (u32, u32)| w >= size.0 && h >= size.1;
+        if !self.context.as_ref().is_some_and(|(s, _)| fits(*s)) {
+            // The context is only replaced once the images decoded with it have finished.
+            while !self.in_flight.is_empty() {
+                self.retire_oldest();
+            }
+            let (old_w, old_h) = self.context.take().map_or((0, 0), |(s, _)| s);
+            let context_size = (size.0.max(old_w), size.1.max(old_h));
+            log::debug!("creating JPEG batch decode context for {context_size:?}");
+            let context = Context::new(&self.config, context_size.0, context_size.1)?;
+            self.context = Some((context_size, context));
+        }
+        let Some((_, context)) = &mut self.context else {
+            unreachable!()
+        };
+
+        let mut surface = match self
+            .free_surfaces
+            .iter()
+            .position(|s| s.size == size && s.formats == formats)
+        {
+            Some(index) => self.free_surfaces.swap_remove(index),
+            None => PooledSurface {
+                size,
+                formats,
+                surface: create_surface(&self.config.d, size.0, size.1, formats)?,
+            },
+        };
+
+        if let Err(e) = decode_picture(
+            context,
+            &mut surface.surface,
+            jpeg,
+            info.color_space(),
+            &DecodeOptions::new(),
+            self.max_slice_data_size,
+        ) {
+            self.release_surface(surface);
+            return Err(e);
+        }
+
+        let ticket = Ticket(self.next_ticket);
+        self.next_ticket += 1;
+        self.in_flight.push_back(Pending {
+            ticket,
+            info,
+            surface,
+        });
+        Ok(ticket)
+    }
+
+    /// Returns the decoded image for `ticket` if it has finished decoding, without blocking.
+    ///
+    /// Returns [`None`] if the image is still being decoded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `ticket` is unknown or has already been retrieved, or if decoding the
+    /// image failed.
+    pub fn poll(&mut self, ticket: Ticket) -> Result<Option<BatchDecodedJpeg>> {
+        if let Some(result) = self.take_completed(ticket) {
+            return result.map(Some);
+        }
+
+        let index = self.in_flight_index(ticket)?;
+        let ready = self.in_flight[index]
+            .surface
+            .surface
+            .sync_timeout(Duration::ZERO);
+        if let Ok(false) = ready {
+            return Ok(None);
+        }
+        let pending = self.in_flight.remove(index).unwrap();
+        ready.map(|_| Some(pending.finish()))
+    }
+
+    /// Waits until the image for `ticket` has finished decoding, and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `ticket` is unknown or has already been retrieved, or if decoding the
+    /// image failed.
+    pub fn wait(&mut self, ticket: Ticket) -> Result<BatchDecodedJpeg> {
+        if let Some(result) = self.take_completed(ticket) {
+            return result;
+        }
+
+        let index = self.in_flight_index(ticket)?;
+        let mut pending = self.in_flight.remove(index).unwrap();
+        pending.surface.surface.sync()?;
+        Ok(pending.finish())
+    }
+
+    /// Waits until any submitted image has finished decoding, and returns it.
+    ///
+    /// Images are returned in the order in which they finish decoding, which is not necessarily
+    /// the order in which they were submitted. Returns [`None`] if there are no pending images.
+    ///
+    /// If decoding an image fails, the error is returned together with the image's [`Ticket`].
+    pub fn next_completed(&mut self) -> Option<(Ticket, Result<BatchDecodedJpeg>)> {
+        if let Some(completed) = self.completed.pop_front() {
+            return Some(completed);
+        }
+
+        if self.in_flight.is_empty() {
+            return None;
+        }
+        loop {
+            // Images can finish out of order, so first look for any image that is already done.
+            // Syncing its surface then does not block, and reports decoding errors.
+            let ready = self
+                .in_flight
+                .iter()
+                .position(|p| !matches!(p.surface.surface.is_ready(), Ok(false)));
+            if let Some(index) = ready {
+                let mut pending = self.in_flight.remove(index).unwrap();
+                let ticket = pending.ticket;
+                let result = pending.surface.surface.sync().map(|()| pending.finish());
+                return Some((ticket, result));
+            }
+
+            // Otherwise, wait in the driver for the oldest image, which is usually the next one to
+            // finish. The timeout bounds how long a later image that finishes first goes unnoticed.
+            let ready = self.in_flight[0].surface.surface.sync_timeout(SYNC_TIMEOUT);
+            if !matches!(ready, Ok(false)) {
+                let pending = self.in_flight.pop_front().unwrap();
+                let ticket = pending.ticket;
+                return Some((ticket, ready.map(|_| pending.finish())));
+            }
+        }
+    }
+
+    /// Returns the [`Surface`] of a retrieved image to the decoder, so that it can be reused for
+    /// later images.
+    ///
+    /// At most `max_in_flight` unused [`Surface`]s are kept.
+    pub fn recycle(&mut self, image: BatchDecodedJpeg) {
+        self.release_surface(image.surface);
+    }
+
+    fn release_surface(&mut self, surface: PooledSurface) {
+        if self.free_surfaces.len() >= self.max_in_flight {
+            self.free_surfaces.remove(0);
+        }
+        self.free_surfaces.push(surface);
+    }
+
+    /// Waits for the oldest in-flight image and moves it to the completed queue.
+    fn retire_oldest(&mut self) {
+        let Some(mut pending) = self.in_flight.pop_front() else {
+            return;
+        };
+        let ticket = pending.ticket;
+        let result = pending.surface.surface.sync().map(|()| pending.finish());
+        self.completed.push_back((ticket, result));
+    }
+
+    fn take_completed(&mut self, ticket: Ticket) -> Option<Result<BatchDecodedJpeg>> {
+        let index = self.completed.iter().position(|(t, _)| *t == ticket)?;
+        self.completed.remove(index).map(|(_, result)| result)
+    }
+
+    fn in_flight_index(&self, ticket: Ticket) -> Result<usize> {
+        self.in_flight
+            .iter()
+            .position(|p| p.ticket == ticket)
+            .ok_or_else(|| Error::from(format!("unknown or already retrieved {ticket:?}")))
+    }
+}
+
+/// A JPEG image decoded by a [`JpegBatchDecoder`].
+pub struct BatchDecodedJpeg {
+    ticket: Ticket,
+    info: JpegInfo,
+    surface: PooledSurface,
+}
+
+impl BatchDecodedJpeg {
+    /// Returns the [`Ticket`] that was returned when this image was submitted.
+    #[inline]
+    pub fn ticket(&self) -> Ticket {
+        self.ticket
+    }
+
+    /// Returns the [`JpegInfo`] of the decoded image.
+    #[inline]
+    pub fn info(&self) -> &JpegInfo {
+        &self.info
+    }
+
+    /// Returns the [`Surface`] containing the decoded image.
+    ///
+    /// The [`Surface`] may be larger than the image. The image is located in its top left corner,
+    /// and the contents of the remaining area are unspecified.
+    #[inline]
+    pub fn surface(&mut self) -> &mut Surface {
+        &mut self.surface.surface
+    }
+
+    /// Returns the [`Surface`] containing the decoded image, consuming `self`.
+    ///
+    /// Use [`JpegBatchDecoder::recycle`] instead of dropping `self` to allow the [`Surface`] to be
+    /// reused.
+    #[inline]
+    pub fn into_surface(self) -> Surface {
+        self.surface.surface
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::run_test;
+
+    use super::*;
+
+    #[test]
+    fn batch_decode() {
+        run_test(|display| {
+            let mut decoder = match JpegBatchDecoder::new(display, 2) {
+                Ok(decoder) => decoder,
+                Err(e) => {
+                    log::warn!("skipping test; JPEG decoding is not supported: {e}");
+                    return;
+                }
+            };
+
+            let mjpeg = std::fs::read("src/jpeg/test-images/mjpeg.jpg").unwrap();
+            let restarts = std::fs::read("src/jpeg/test-images/restarts.jpg").unwrap();
+
+            let tickets = [
+                decoder.submit(&restarts).unwrap(),
+                decoder.submit(&mjpeg).unwrap(),
+                decoder.submit(&restarts).unwrap(),
+            ];
+            assert_eq!(decoder.pending(), 3);
+
+            let image = decoder.wait(tickets[1]).unwrap();
+            assert_eq!(image.info().width(), 960);
+            decoder.recycle(image);
+            assert!(decoder.wait(tickets[1]).is_err());
+
+            let mut completed = Vec::new();
+            while let Some((ticket, result)) = decoder.next_completed() {
+                assert_eq!(result.unwrap().info().width(), 33);
+                completed.push(ticket);
+            }
+            completed.sort_by_key(|t| t.0);
+            assert_eq!(completed, [tickets[0], tickets[2]]);
+            assert_eq!(decoder.pending(), 0);
+        });
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{
+    config::Config,
+    context::Context,
+    display::Display,
+    surface::{RTFormat, Surface},
+    Entrypoint, Error, PixelFormat, Profile, Result,
+};
+
+use super::{
+    create_surface, decode_picture, probe_supported, query_dec_jpeg_features, DecodeOptions,
+    JpegInfo, DEC_JPEG_CROP,
+};
+
+/// Image dimensions are rounded up to a multiple of this, so that similarly sized images can share
+/// a cache entry.
+const SIZE_GRANULARITY: u32 = 256;
+
+const DEFAULT_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+/// A JPEG decoder that handles images of any size and chroma subsampling.
+///
+/// Unlike [`JpegDecodeSession`][super::JpegDecodeSession], which is tied to a single image size,
+/// [`JpegDecoder`] keeps a cache of [`Context`]s and [`Surface`]s, keyed by image size (rounded up
+/// to a multiple of 256 pixels) and [`Surface`] format. When the estimated memory use of the
+/// cached [`Surface`]s exceeds the configured budget, the least recently used entries are
+/// evicted.
+pub struct JpegDecoder {
+    config: Config,
+    features: u32,
+    memory_budget: usize,
+    max_slice_data_size: Option<usize>,
+    entries: Vec<CacheEntry>,
+    /// Incremented on every decode, used to determine the least recently used cache entry.
+    clock: u64,
+}
+
+struct CacheEntry {
+    /// Rounded-up image size, used as the size of `context`.
+    context_size: (u32, u32),
+    /// Rounded-up size of `surface`, which can differ from the image size when rotating or
+    /// cropping.
+    surface_size: (u32, u32),
+    formats: (RTFormat, PixelFormat),
+    context: Context,
+    surface: Surface,
+    last_used: u64,
+}
+
+impl CacheEntry {
+    fn memory_size(&self) -> usize {
+        let (width, height) = self.surface_size;
+        surface_memory_size(width, height, self.formats.0)
+    }
+}
+
+impl JpegDecoder {
+    /// Creates a new [`JpegDecoder`] with an empty cache and a memory budget of 256 MiB.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the implementation does not support JPEG decoding.
+    pub fn new(display: &Display) -> Result<Self> {
+        let config = Config::new(display, Profile::JPEGBaseline, Entrypoint::VLD)?;
+        Ok(Self {
+            config,
+            features: query_dec_jpeg_features(display)?,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            max_slice_data_size: None,
+            entries: Vec::new(),
+            clock: 0,
+        })
+    }
+
+    /// Returns the memory budget for cached [`Surface`]s, in bytes.
+    #[inline]
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget
+    }
+
+    /// Sets the memory budget for cached [`Surface`]s, in bytes.
+    ///
+    /// If the cache currently exceeds the new budget, the least recently used entries are evicted
+    /// immediately.
+    ///
+    /// The budget only accounts for the estimated size of the [`Surface`]s; driver-internal
+    /// allocations belonging to the [`Context`]s are not included. A single image that exceeds
+    /// the budget on its own can still be decoded, but will evict all other entries.
+    pub fn set_memory_budget(&mut self, bytes: usize) {
+        self.memory_budget = bytes;
+        self.evict(0);
+    }
+
+    /// Returns the estimated memory used by cached [`Surface`]s, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.entries.iter().map(CacheEntry::memory_size).sum()
+    }
+
+    /// Returns the number of cached [`Context`] and [`Surface`] pairs.
+    #[inline]
+    pub fn cached_sessions(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the implementation supports decoding a part of the image, as requested by
+    /// [`DecodeOptions::with_crop`].
+    #[inline]
+    pub fn supports_crop(&self) -> bool {
+        self.features & DEC_JPEG_CROP != 0
+    }
+
+    /// Sets the maximum amount of scan data to submit to the driver in one piece.
+    ///
+    /// See [`JpegDecodeSession::set_max_slice_data_size`] for details.
+    ///
+    /// [`JpegDecodeSession::set_max_slice_data_size`]:
+    /// super::JpegDecodeSession::set_max_slice_data_size
+    #[inline]
+    pub fn set_max_slice_data_size(&mut self, size: Option<usize>) {
+        self.max_slice_data_size = size;
+    }
+
+    /// Drops all cached [`Context`]s and [`Surface`]s.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Decodes a baseline JPEG image.
+    ///
+    /// The returned [`DecodedJpeg`] borrows a cached [`Surface`], which will be overwritten by
+    /// later calls to [`JpegDecoder::decode`].
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the JPEG is malformed or unsupported, or VA-API returns
+    /// an error during decoding.
+    pub fn decode(&mut self, jpeg: &[u8]) -> Result<DecodedJpeg<'_>> {
+        self.decode_with_options(jpeg, &DecodeOptions::new())
+    }
+
+    /// Decodes a baseline JPEG image with the given [`DecodeOptions`].
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the JPEG is malformed or unsupported, the options are
+    /// invalid or not supported by the implementation, or VA-API returns an error during
+    /// decoding.
+    pub fn decode_with_options(
+        &mut self,
+        jpeg: &[u8],
+        options: &DecodeOptions,
+    ) -> Result<DecodedJpeg<'_>> {
+        let (info, formats) = probe_supported(jpeg)?;
+        if info.width() == 0 || info.height() == 0 {
+            return Err(Error::from(format!(
+                "invalid image dimensions {}x{}",
+                info.width(),
+                info.height()
+            )));
+        }
+
+        let ((width, height), formats) = options.resolve(&info, formats, self.features)?;
+        let round = |size: u32| size.next_multiple_of(SIZE_GRANULARITY);
+        let context_size = (round(info.width().into()), round(info.height().into()));
+        let surface_size = (round(width), round(height));
+
+        let index = match self.entries.iter().position(|e| {
+            e.context_size == context_size && e.surface_size == surface_size && e.formats == formats
+        }) {
+            Some(index) => index,
+            None => {
+                let (width, height) = surface_size;
+                self.evict(surface_memory_size(width, height, formats.0));
+
+                log::debug!(
+                    "creating JPEG decode context for {context_size:?}, \
+                    {surface_size:?} {formats:?}"
+                );
+                let context = Context::new(&self.config, context_size.0, context_size.1)?;
+                let surface = create_surface(&self.config.d, width, height, formats)?;
+                self.entries.push(CacheEntry {
+                    context_size,
+                    surface_size,
+                    formats,
+                    context,
+                    surface,
+                    last_used: 0,
+                });
+                self.entries.len() - 1
+            }
+        };
+
+        self.clock += 1;
+        let entry = &mut self.entries[index];
+        entry.last_used = self.clock;
+
+        decode_picture(
+            &mut entry.context,
+            &mut entry.surface,
+            jpeg,
+            info.color_space(),
+            options,
+            self.max_slice_data_size,
+        )?;
+
+        Ok(DecodedJpeg {
+            info,
+            size: (width as u16, height as u16),
+            surface: &mut entry.surface,
+        })
+    }
+
+    /// Evicts least recently used entries until `additional` bytes fit into the memory budget.
+    fn evict(&mut self, additional: usize) {
+        while !self.entries.is_empty() && self.memory_usage() + additional > self.memory_budget {
+            let (index, _) = self
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, e)| e.last_used)
+                .unwrap();
+            let entry = self.entries.swap_remove(index);
+            log::debug!(
+                "evicting JPEG decode context for {:?}, {:?} {:?}",
+                entry.context_size,
+                entry.surface_size,
+                entry.formats
+            );
+        }
+    }
+}
+
+/// A JPEG image decoded by a [`JpegDecoder`].
+pub struct DecodedJpeg<'a> {
+    info: JpegInfo,
+    /// Size of the output image, after rotation and cropping.
+    size: (u16, u16),
+    surface: &'a mut Surface,
+}
+
+impl<'a> DecodedJpeg<'a> {
+    /// Returns the [`JpegInfo`] of the decoded image.
+    #[inline]
+    pub fn info(&self) -> &JpegInfo {
+        &self.info
+    }
+
+    /// Returns the width of the decoded image, after applying rotation and cropping.
+    #[inline]
+    pub fn width(&self) -> u16 {
+        self.size.0
+    }
+
+    /// Returns the height of the decoded image, after applying rotation and cropping.
+    #[inline]
+    pub fn height(&self) -> u16 {
+        self.size.1
+    }
+
+    /// Returns the [`Surface`] containing the decoded image.
+    ///
+    /// The [`Surface`] may be larger than the image. The image is located in its top left corner,
+    /// and the contents of the remaining area are unspecified.
+    #[inline]
+    pub fn surface(&mut self) -> &mut Surface {
+        self.surface
+    }
+
+    /// Returns the [`Surface`] containing the decoded image, with the lifetime of the
+    /// [`JpegDecoder`] borrow.
+    #[inline]
+    pub fn into_surface(self) -> &'a mut Surface {
+        self.surface
+    }
+}
+
+/// Estimates the memory used by a [`Surface`] of the given size and [`RTFormat`].
+fn surface_memory_size(width: u32, height: u32, format: RTFormat) -> usize {
+    // Bytes per 2 pixels.
+    let bytes = if format == RTFormat::YUV400 {
+        2
+    } else if format == RTFormat::YUV420 || format == RTFormat::YUV411 {
+        3
+    } else if format == RTFormat::YUV422 {
+        4
+    } else {
+        6
+    };
+    width as usize * height as usize * bytes / 2
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::run_test;
+
+    use super::*;
+
+    #[test]
+    fn decoder_cache() {
+        run_test(|display| {
+            let mut decoder = match JpegDecoder::new(display) {
+                Ok(decoder) => decoder,
+                Err(e) => {
+                    log::warn!("skipping test; JPEG decoding is not supported: {e}");
+                    return;
+                }
+            };
+
+            let mjpeg = std::fs::read("src/jpeg/test-images/mjpeg.jpg").unwrap();
+            let restarts = std::fs::read("src/jpeg/test-images/restarts.jpg").unwrap();
+
+            let decoded = decoder.decode(&mjpeg).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (960, 720));
+            decoded.into_surface().sync().unwrap();
+            decoder.decode(&restarts).unwrap();
+            decoder.decode(&mjpeg).unwrap();
+            assert_eq!(decoder.cached_sessions(), 2);
+
+            // Shrinking the budget to one entry evicts the least recently used one.
+            let mjpeg_size = surface_memory_size(1024, 768, RTFormat::YUV422);
+            decoder.set_memory_budget(mjpeg_size);
+            assert_eq!(decoder.cached_sessions(), 1);
+            assert_eq!(decoder.memory_usage(), mjpeg_size);
+        });
+    }
+
+    #[test]
+    fn invalid_huffman_selector() {
+        run_test(|display| {
+            let mut decoder = match JpegDecoder::new(display) {
+                Ok(decoder) => decoder,
+                Err(e) => {
+                    log::warn!("skipping test; JPEG decoding is not supported: {e}");
+                    return;
+                }
+            };
+
+            // Make the first scan component select DC and AC table 2, which does not exist.
+            let mut jpeg = std::fs::read("src/jpeg/test-images/mjpeg.jpg").unwrap();
+            let sos = jpeg.windows(2).position(|w| w == [0xFF, 0xDA]).unwrap();
+            jpeg[sos + 6] = 0x22;
+            assert!(decoder.decode(&jpeg).is_err());
+        });
+    }
+}
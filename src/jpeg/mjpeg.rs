@@ -0,0 +1,228 @@
+use crate::{
+    config::Config,
+    context::Context,
+    display::Display,
+    surface::{RTFormat, Surface},
+    Entrypoint, Error, PixelFormat, Profile, Result,
+};
+
+use super::{create_surface, decode_picture, probe_supported, DecodeOptions, JpegInfo};
+
+const DEFAULT_RING_SIZE: usize = 4;
+
+/// A decoder for Motion-JPEG streams, like the ones produced by USB webcams.
+///
+/// MJPEG frames often omit the DHT segment, relying on the decoder to use the default Huffman
+/// tables from the JPEG specification. [`MjpegDecoder`] handles this transparently.
+///
+/// Frames are decoded into a ring of [`Surface`]s, so that several frames can be in flight at
+/// once: [`MjpegDecoder::decode`] only submits the decode operation, without waiting for it to
+/// complete. A decoded frame stays available until the ring wraps around and its [`Surface`] is
+/// reused for a later frame.
+///
+/// The [`Context`] and [`Surface`]s are reused across frames of the same size and format, and
+/// recreated when the stream changes resolution or format.
+pub struct MjpegDecoder {
+    config: Config,
+    ring_size: usize,
+    stream: Option<Stream>,
+    /// Number of frames decoded so far, used to identify the frame stored in each slot.
+    frame_count: u64,
+}
+
+struct Stream {
+    width: u16,
+    height: u16,
+    formats: (RTFormat, PixelFormat),
+    context: Context,
+    slots: Vec<Slot>,
+    next_slot: usize,
+}
+
+struct Slot {
+    surface: Surface,
+    /// Frame number of the frame currently stored in `surface`, if any.
+    frame: Option<u64>,
+}
+
+impl MjpegDecoder {
+    /// Creates a new [`MjpegDecoder`] with the default ring size of 4 [`Surface`]s.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the implementation does not support JPEG decoding.
+    pub fn new(display: &Display) -> Result<Self> {
+        Self::with_ring_size(display, DEFAULT_RING_SIZE)
+    }
+
+    /// Creates a new [`MjpegDecoder`] that decodes into a ring of `ring_size` [`Surface`]s.
+    ///
+    /// At most `ring_size` decoded frames are available at the same time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `ring_size` is 0, or if the implementation does not support JPEG
+    /// decoding.
+    pub fn with_ring_size(display: &Display, ring_size: usize) -> Result<Self> {
+        if ring_size == 0 {
+            return Err(Error::from("MJPEG surface ring size must not be 0"));
+        }
+
+        Ok(Self {
+            config: Config::new(display, Profile::JPEGBaseline, Entrypoint::VLD)?,
+            ring_size,
+            stream: None,
+            frame_count: 0,
+        })
+    }
+
+    /// Returns the number of [`Surface`]s in the ring.
+    #[inline]
+    pub fn ring_size(&self) -> usize {
+        self.ring_size
+    }
+
+    /// Submits a Motion-JPEG frame for decoding.
+    ///
+    /// This does not wait for decoding to finish. Use [`MjpegDecoder::surface`] to access the
+    /// decoded [`Surface`], and synchronize on it before reading its contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the frame is malformed or unsupported, or if VA-API returns an error.
+    pub fn decode(&mut self, frame: &[u8]) -> Result<MjpegFrame> {
+        let (info, formats) = probe_supported(frame)?;
+        if info.width() == 0 || info.height() == 0 {
+            return Err(Error::from(format!(
+                "invalid frame dimensions {}x{}",
+                info.width(),
+                info.height()
+            )));
+        }
+
+        let stream = match &mut self.stream {
+            Some(stream)
+                if (stream.width, stream.height, stream.formats)
+                    == (info.width(), info.height(), formats) =>
+            {
+                stream
+            }
+            stream => {
+                log::debug!(
+                    "creating MJPEG decode context for {}x{} {formats:?}",
+                    info.width(),
+                    info.height()
+                );
+                // Drop the old objects before allocating new ones.
+                *stream = None;
+
+                let (width, height) = (u32::from(info.width()), u32::from(info.height()));
+                let context = Context::new(&self.config, width, height)?;
+                let slots = (0..self.ring_size)
+                    .map(|_| {
+                        Ok(Slot {
+                            surface: create_surface(&self.config.d, width, height, formats)?,
+                            frame: None,
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                stream.insert(Stream {
+                    width: info.width(),
+                    height: info.height(),
+                    formats,
+                    context,
+                    slots,
+                    next_slot: 0,
+                })
+            }
+        };
+
+        let index = stream.next_slot;
+        let slot = &mut stream.slots[index];
+        // Invalidate the slot first, in case decoding fails.
+        slot.frame = None;
+        decode_picture(
+            &mut stream.context,
+            &mut slot.surface,
+            frame,
+            info.color_space(),
+            &DecodeOptions::new(),
+            None,
+        )?;
+
+        let number = self.frame_count;
+        self.frame_count += 1;
+        slot.frame = Some(number);
+        stream.next_slot = (index + 1) % stream.slots.len();
+
+        Ok(MjpegFrame {
+            info,
+            slot: index,
+            number,
+        })
+    }
+
+    /// Returns the [`Surface`] containing a previously decoded frame.
+    ///
+    /// Returns [`None`] if the [`Surface`] has since been reused for a later frame, or the
+    /// [`Surface`]s have been recreated because the stream changed resolution or format.
+    pub fn surface(&mut self, frame: &MjpegFrame) -> Option<&mut Surface> {
+        let slot = self.stream.as_mut()?.slots.get_mut(frame.slot)?;
+        (slot.frame == Some(frame.number)).then_some(&mut slot.surface)
+    }
+}
+
+/// A frame submitted to an [`MjpegDecoder`].
+#[derive(Debug, Clone)]
+pub struct MjpegFrame {
+    info: JpegInfo,
+    slot: usize,
+    number: u64,
+}
+
+impl MjpegFrame {
+    /// Returns the [`JpegInfo`] of the frame.
+    #[inline]
+    pub fn info(&self) -> &JpegInfo {
+        &self.info
+    }
+
+    /// Returns the sequence number of this frame, counting from 0.
+    #[inline]
+    pub fn number(&self) -> u64 {
+        self.number
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::run_test;
+
+    use super::*;
+
+    #[test]
+    fn mjpeg_ring() {
+        run_test(|display| {
+            let mut decoder = match MjpegDecoder::with_ring_size(display, 2) {
+                Ok(decoder) => decoder,
+                Err(e) => {
+                    log::warn!("skipping test; JPEG decoding is not supported: {e}");
+                    return;
+                }
+            };
+
+            // This frame has no DHT segment.
+            let mjpeg = std::fs::read("src/jpeg/test-images/mjpeg.jpg").unwrap();
+            let first = decoder.decode(&mjpeg).unwrap();
+            let second = decoder.decode(&mjpeg).unwrap();
+            assert_eq!((first.number(), second.number()), (0, 1));
+            decoder.surface(&first).unwrap().sync().unwrap();
+
+            // The third frame reuses the first frame's surface.
+            let third = decoder.decode(&mjpeg).unwrap();
+            assert!(decoder.surface(&first).is_none());
+            decoder.surface(&second).unwrap().sync().unwrap();
+            decoder.surface(&third).unwrap().sync().unwrap();
+        });
+    }
+}
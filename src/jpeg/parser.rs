@@ -1,11 +1,20 @@
+//! A parser for the marker segments of a JPEG file.
+//!
+//! Besides the segments needed for decoding, this also parses common metadata segments, so that
+//! metadata like the EXIF orientation or the ICC color profile can be extracted from an image
+//! that is decoded in hardware.
+//!
+//! Field and method names follow the ITU-T T.81 specification.
+
 #![allow(non_snake_case)]
 
-use std::{fmt, mem};
+use std::{fmt, mem, str};
 
 use bytemuck::{AnyBitPattern, Pod, Zeroable};
 
-use crate::{error::Error, Result};
+use crate::Result;
 
+/// Iterates over the marker segments of a JPEG file.
 pub struct JpegParser<'a> {
     reader: Reader<'a>,
 }
@@ -13,11 +22,23 @@ pub struct JpegParser<'a> {
 impl<'a> JpegParser<'a> {
     pub fn new(buf: &'a [u8]) -> Self {
         Self {
-            reader: Reader { buf, position: 0 },
+            reader: Reader {
+                buf,
+                base: 0,
+                position: 0,
+            },
         }
     }
 
-    pub fn next_segment(&mut self) -> Result<Option<Segment<'a>>> {
+    /// Parses the next marker segment.
+    ///
+    /// Returns [`None`] when the end of the input has been reached.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`JpegError`] describing the location of the problem if the input is malformed.
+    /// This method never panics, regardless of the input.
+    pub fn next_segment(&mut self) -> Result<Option<Segment<'a>>, JpegError> {
         if self.reader.remaining().is_empty() {
             return Ok(None);
         }
@@ -26,9 +47,29 @@ impl<'a> JpegParser<'a> {
 
         let position = self.reader.position - 1;
         let marker = self.reader.read_u8()?;
+        let kind = self.read_segment_kind(marker, position).map_err(|mut e| {
+            e.segment = Some(marker);
+            e
+        })?;
 
+        Ok(Some(Segment {
+            pos: position,
+            kind,
+        }))
+    }
+
+    fn read_segment_kind(
+        &mut self,
+        marker: u8,
+        position: usize,
+    ) -> Result<SegmentKind<'a>, JpegError> {
         let kind = match marker {
-            0x00 => return Err(Error::from("invalid ff 00 marker".to_string())),
+            0x00 => {
+                return Err(JpegError::new(
+                    position,
+                    JpegErrorKind::InvalidMarker(marker),
+                ))
+            }
             0xD8 => SegmentKind::Soi,
             0xD9 => SegmentKind::Eoi,
             0xDB => SegmentKind::Dqt(self.read_dqt()?),
@@ -38,25 +79,24 @@ impl<'a> JpegParser<'a> {
             }
             0xDA => SegmentKind::Sos(self.read_sos()?),
             0xDD => SegmentKind::Dri(self.read_dri()?),
+            0xDC => SegmentKind::Dnl(self.read_dnl()?),
+            0xFE => SegmentKind::Com(Com(self.reader.read_segment()?.remaining())),
+            0xE0..=0xEF => self.read_app(marker)?,
             _ => SegmentKind::Other {
                 marker,
                 data: self.reader.read_segment()?.remaining(),
             },
         };
-
-        Ok(Some(Segment {
-            pos: position,
-            kind,
-        }))
+        Ok(kind)
     }
 
-    fn read_dqt(&mut self) -> Result<Dqt<'a>> {
+    fn read_dqt(&mut self) -> Result<Dqt<'a>, JpegError> {
         let mut seg = self.reader.read_segment()?;
         let inner = seg.read_remaining_objs::<QuantizationTable>()?;
         Ok(Dqt(inner))
     }
 
-    fn read_dht(&mut self) -> Result<Dht<'a>> {
+    fn read_dht(&mut self) -> Result<Dht<'a>, JpegError> {
         const MIN_DHT_LEN: usize = 18; // Tc+Th + 16 length bytes + at least one symbol-length assignment
 
         let mut seg = self.reader.read_segment()?;
@@ -74,7 +114,7 @@ impl<'a> JpegParser<'a> {
         Ok(Dht { tables })
     }
 
-    fn read_sof(&mut self, sof: u8) -> Result<Sof<'a>> {
+    fn read_sof(&mut self, sof: u8) -> Result<Sof<'a>, JpegError> {
         let mut seg = self.reader.read_segment()?;
         let P = seg.read_u8()?;
         let Y = seg.read_u16()?;
@@ -90,7 +130,7 @@ impl<'a> JpegParser<'a> {
         })
     }
 
-    fn read_sos(&mut self) -> Result<Sos<'a>> {
+    fn read_sos(&mut self) -> Result<Sos<'a>, JpegError> {
         let mut seg = self.reader.read_segment()?;
         let num_components = seg.read_u8()?;
         let components = seg.read_objs(num_components.into())?;
@@ -136,15 +176,236 @@ impl<'a> JpegParser<'a> {
         })
     }
 
-    fn read_dri(&mut self) -> Result<Dri> {
+    fn read_dri(&mut self) -> Result<Dri, JpegError> {
         let mut seg = self.reader.read_segment()?;
         let Ri = seg.read_u16()?;
         Ok(Dri { Ri })
     }
+
+    fn read_dnl(&mut self) -> Result<Dnl, JpegError> {
+        let mut seg = self.reader.read_segment()?;
+        let NL = seg.read_u16()?;
+        Ok(Dnl { NL })
+    }
+
+    /// Reads an **APPn** segment.
+    ///
+    /// Known application segments are parsed, but if they turn out to be malformed, they are
+    /// returned as [`SegmentKind::Other`] instead of failing, since they aren't needed for
+    /// decoding.
+    fn read_app(&mut self, marker: u8) -> Result<SegmentKind<'a>, JpegError> {
+        let data = self.reader.read_segment()?.remaining();
+        let kind = match marker {
+            0xE0 => data
+                .strip_prefix(b"JFIF\0")
+                .and_then(Jfif::parse)
+                .map(SegmentKind::Jfif),
+            0xE1 => {
+                if let Some(tiff) = data.strip_prefix(b"Exif\0\0") {
+                    Some(SegmentKind::Exif(Exif { tiff }))
+                } else {
+                    data.strip_prefix(b"http://ns.adobe.com/xap/1.0/\0")
+                        .map(|xml| SegmentKind::Xmp(Xmp(xml)))
+                }
+            }
+            0xE2 => data
+                .strip_prefix(b"ICC_PROFILE\0")
+                .and_then(|data| match data {
+                    [seq_no, num_chunks, data @ ..] => Some(SegmentKind::Icc(IccChunk {
+                        seq_no: *seq_no,
+                        num_chunks: *num_chunks,
+                        data,
+                    })),
+                    _ => None,
+                }),
+            0xEE => data
+                .strip_prefix(b"Adobe")
+                .and_then(Adobe::parse)
+                .map(SegmentKind::Adobe),
+            _ => None,
+        };
+        Ok(kind.unwrap_or(SegmentKind::Other { marker, data }))
+    }
+}
+
+/// Extracts the ICC color profile from a JPEG file.
+///
+/// ICC profiles are stored in one or more **APP2** segments, which are reassembled by this
+/// function. Returns [`None`] if the image does not contain an ICC profile.
+///
+/// # Errors
+///
+/// Returns an error if the JPEG is malformed, or if the ICC profile chunks are inconsistent or
+/// incomplete.
+pub fn icc_profile(jpeg: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut parser = JpegParser::new(jpeg);
+    let mut chunks = Vec::new();
+    while let Some(segment) = parser.next_segment()? {
+        match segment.kind {
+            SegmentKind::Icc(chunk) => chunks.push((segment.pos, chunk)),
+            // Metadata segments always precede the image data.
+            SegmentKind::Sos(_) | SegmentKind::Eoi => break,
+            _ => {}
+        }
+    }
+
+    let Some((first_pos, first)) = chunks.first() else {
+        return Ok(None);
+    };
+    let num_chunks = first.num_chunks();
+    if chunks.len() != usize::from(num_chunks)
+        || chunks.iter().any(|(_, c)| c.num_chunks != num_chunks)
+    {
+        // Point at the first chunk that disagrees with the first one, if there is one.
+        let pos = chunks
+            .iter()
+            .find(|(_, c)| c.num_chunks != num_chunks)
+            .map_or(*first_pos, |(pos, _)| *pos);
+        return Err(JpegError::in_segment(
+            pos,
+            0xE2,
+            JpegErrorKind::IccChunkCount {
+                expected: num_chunks,
+                found: chunks.len(),
+            },
+        )
+        .into());
+    }
+
+    chunks.sort_by_key(|(_, c)| c.seq_no);
+    for (i, (pos, chunk)) in chunks.iter().enumerate() {
+        if usize::from(chunk.seq_no) != i + 1 {
+            return Err(JpegError::in_segment(
+                *pos,
+                0xE2,
+                JpegErrorKind::MissingIccChunk(i as u8 + 1),
+            )
+            .into());
+        }
+    }
+
+    Ok(Some(
+        chunks.iter().flat_map(|(_, c)| c.data).copied().collect(),
+    ))
+}
+
+/// An error encountered while parsing a JPEG file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JpegError {
+    offset: usize,
+    segment: Option<u8>,
+    kind: JpegErrorKind,
+}
+
+impl JpegError {
+    fn new(offset: usize, kind: JpegErrorKind) -> Self {
+        Self {
+            offset,
+            segment: None,
+            kind,
+        }
+    }
+
+    /// Creates an error for a missing segment, detected at `offset`.
+    pub(crate) fn new_missing(offset: usize, marker: u8) -> Self {
+        Self::new(offset, JpegErrorKind::MissingSegment(marker))
+    }
+
+    /// Creates an error for a problem with the contents of the segment with the given marker.
+    pub(crate) fn in_segment(offset: usize, marker: u8, kind: JpegErrorKind) -> Self {
+        Self {
+            offset,
+            segment: Some(marker),
+            kind,
+        }
+    }
+
+    /// Returns the byte offset in the input at which the error was detected.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the marker of the segment that failed to parse.
+    ///
+    /// Returns [`None`] if the error occurred while searching for the next marker.
+    #[inline]
+    pub fn segment(&self) -> Option<u8> {
+        self.segment
+    }
+
+    #[inline]
+    pub fn kind(&self) -> &JpegErrorKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for JpegError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {:#x}", self.kind, self.offset)?;
+        if let Some(marker) = self.segment {
+            write!(f, " in segment FF{marker:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for JpegError {}
+
+/// The kind of a [`JpegError`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum JpegErrorKind {
+    /// The input ended in the middle of a segment.
+    UnexpectedEof,
+    /// A marker that is not allowed in a JPEG file was encountered.
+    InvalidMarker(u8),
+    /// The length field of a segment is too small to be valid.
+    InvalidLength(u16),
+    /// A required segment is missing. Contains the marker of the missing segment.
+    MissingSegment(u8),
+    /// The frame header does not define any color components.
+    NoComponents,
+    /// A color component uses sampling factors outside of the valid range 1-4.
+    InvalidSamplingFactors { component: u8, h: u8, v: u8 },
+    /// The sample precision is not valid for the frame type.
+    InvalidPrecision(u8),
+    /// The number of ICC profile chunks does not match the number the chunks declare.
+    IccChunkCount { expected: u8, found: usize },
+    /// An ICC profile chunk is missing. Contains its 1-based sequence number.
+    MissingIccChunk(u8),
+}
+
+impl fmt::Display for JpegErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEof => f.write_str("unexpected end of data"),
+            Self::InvalidMarker(marker) => write!(f, "invalid marker FF{marker:02X}"),
+            Self::InvalidLength(len) => write!(f, "invalid segment length {len}"),
+            Self::MissingSegment(marker) => write!(f, "missing segment FF{marker:02X}"),
+            Self::NoComponents => f.write_str("frame has no color components"),
+            Self::InvalidSamplingFactors { component, h, v } => {
+                write!(
+                    f,
+                    "invalid sampling factors {h}x{v} for component {component}"
+                )
+            }
+            Self::InvalidPrecision(precision) => {
+                write!(f, "invalid sample precision of {precision} bits")
+            }
+            Self::IccChunkCount { expected, found } => write!(
+                f,
+                "ICC profile is split into {expected} chunks, but {found} were found"
+            ),
+            Self::MissingIccChunk(seq_no) => write!(f, "ICC profile chunk {seq_no} is missing"),
+        }
+    }
 }
 
 struct Reader<'a> {
     buf: &'a [u8],
+    /// Offset of `buf` in the input of the [`JpegParser`].
+    base: usize,
     position: usize,
 }
 
@@ -153,18 +414,18 @@ impl<'a> Reader<'a> {
         &self.buf[self.position..]
     }
 
-    fn peek_u8(&self, offset: usize) -> Result<u8> {
-        if self.position + offset >= self.buf.len() {
-            Err(Error::from(
-                "reached end of data while decoding JPEG stream".to_string(),
-            ))
-        } else {
-            let byte = self.buf[self.position + offset];
-            Ok(byte)
+    fn error(&self, kind: JpegErrorKind) -> JpegError {
+        JpegError::new(self.base + self.position, kind)
+    }
+
+    fn peek_u8(&self, offset: usize) -> Result<u8, JpegError> {
+        match self.remaining().get(offset) {
+            Some(byte) => Ok(*byte),
+            None => Err(self.error(JpegErrorKind::UnexpectedEof)),
         }
     }
 
-    fn read_u8(&mut self) -> Result<u8> {
+    fn read_u8(&mut self) -> Result<u8, JpegError> {
         let res = self.peek_u8(0);
         if res.is_ok() {
             self.position += 1;
@@ -172,81 +433,65 @@ impl<'a> Reader<'a> {
         res
     }
 
-    fn read_u16(&mut self) -> Result<u16> {
+    fn read_u16(&mut self) -> Result<u16, JpegError> {
         let b = [self.read_u8()?, self.read_u8()?];
         Ok(u16::from_be_bytes(b))
     }
 
-    fn read_slice(&mut self, count: usize) -> Result<&'a [u8]> {
-        if self.remaining().len() < count {
-            Err(Error::from(
-                "reached end of data while decoding JPEG stream".to_string(),
-            ))
-        } else {
-            let slice = &self.remaining()[..count];
-            self.position += count;
-            Ok(slice)
+    fn read_slice(&mut self, count: usize) -> Result<&'a [u8], JpegError> {
+        match self.remaining().get(..count) {
+            Some(slice) => {
+                self.position += count;
+                Ok(slice)
+            }
+            None => Err(self.error(JpegErrorKind::UnexpectedEof)),
         }
     }
 
-    fn read_obj<T: AnyBitPattern>(&mut self) -> Result<&'a T> {
-        assert_eq!(mem::align_of::<T>(), 1);
-
-        if self.remaining().len() < mem::size_of::<T>() {
-            return Err(Error::from(
-                "reached end of data while decoding JPEG stream".to_string(),
-            ));
-        }
-
-        let object = bytemuck::from_bytes(&self.remaining()[..mem::size_of::<T>()]);
+    fn read_obj<T: AnyBitPattern>(&mut self) -> Result<&'a T, JpegError> {
+        const { assert!(mem::align_of::<T>() == 1) };
 
-        self.position += mem::size_of::<T>();
-        Ok(object)
+        let bytes = self.read_slice(mem::size_of::<T>())?;
+        Ok(bytemuck::from_bytes(bytes))
     }
 
-    fn read_remaining_objs<T: AnyBitPattern>(&mut self) -> Result<&'a [T]> {
+    fn read_remaining_objs<T: AnyBitPattern>(&mut self) -> Result<&'a [T], JpegError> {
         let count = self.remaining().len() / mem::size_of::<T>();
         self.read_objs(count)
     }
 
-    fn read_objs<T: AnyBitPattern>(&mut self, count: usize) -> Result<&'a [T]> {
-        assert_eq!(mem::align_of::<T>(), 1);
+    fn read_objs<T: AnyBitPattern>(&mut self, count: usize) -> Result<&'a [T], JpegError> {
+        const { assert!(mem::align_of::<T>() == 1) };
 
-        let byte_count = count * mem::size_of::<T>();
-        let slice = bytemuck::cast_slice(&self.remaining()[..byte_count]);
-        self.position += byte_count;
-        Ok(slice)
+        let bytes = self.read_slice(count * mem::size_of::<T>())?;
+        Ok(bytemuck::cast_slice(bytes))
     }
 
-    fn read_length(&mut self) -> Result<u16> {
+    fn read_length(&mut self) -> Result<u16, JpegError> {
         let len = self.read_u16()?;
         if len < 2 {
-            return Err(Error::from(format!("invalid segment length {len}")));
+            self.position -= 2;
+            return Err(self.error(JpegErrorKind::InvalidLength(len)));
         }
         Ok(len)
     }
 
-    fn read_segment(&mut self) -> Result<Reader<'a>> {
+    fn read_segment(&mut self) -> Result<Reader<'a>, JpegError> {
         let len = usize::from(self.read_length()?) - 2;
-        if self.remaining().len() < len {
-            return Err(Error::from(
-                "reached end of data while decoding JPEG stream".to_string(),
-            ));
-        }
-
-        let r = Reader {
-            buf: &self.remaining()[..len],
+        let base = self.base + self.position;
+        let buf = self.read_slice(len)?;
+        Ok(Reader {
+            buf,
+            base,
             position: 0,
-        };
-        self.position += len;
-        Ok(r)
+        })
     }
 }
 
+/// A marker segment in a JPEG file.
 #[derive(Debug)]
 pub struct Segment<'a> {
     /// Offset of the segment's marker in the input buffer.
-    #[allow(dead_code)] // used in tests
     pub pos: usize,
     pub kind: SegmentKind<'a>,
 }
@@ -257,11 +502,18 @@ pub enum SegmentKind<'a> {
     Dqt(Dqt<'a>),
     Dht(Dht<'a>),
     Dri(Dri),
+    Dnl(Dnl),
     Sof(Sof<'a>),
     Sos(Sos<'a>),
     Soi,
     Eoi,
-    #[allow(dead_code)] // used in tests
+    Com(Com<'a>),
+    Jfif(Jfif<'a>),
+    Exif(Exif<'a>),
+    Xmp(Xmp<'a>),
+    Icc(IccChunk<'a>),
+    Adobe(Adobe),
+    /// A segment that is not parsed further, including unknown **APPn** segments.
     Other {
         marker: u8,
         data: &'a [u8],
@@ -403,6 +655,333 @@ impl Dri {
     }
 }
 
+/// **DNL** Define Number of Lines.
+///
+/// Only allowed after the first scan, when the frame header specifies a height of 0.
+#[derive(Debug, Clone, Copy)]
+pub struct Dnl {
+    NL: u16,
+}
+
+impl Dnl {
+    /// Returns the number of lines in the frame.
+    #[inline]
+    pub fn NL(&self) -> u16 {
+        self.NL
+    }
+}
+
+/// **COM** Comment.
+#[derive(Clone, Copy)]
+pub struct Com<'a>(&'a [u8]);
+
+impl<'a> Com<'a> {
+    /// Returns the raw comment bytes.
+    ///
+    /// The encoding is not specified, but comments are typically ASCII or UTF-8 text.
+    #[inline]
+    pub fn data(&self) -> &'a [u8] {
+        self.0
+    }
+
+    /// Returns the comment as a string, if it is valid UTF-8.
+    #[inline]
+    pub fn as_str(&self) -> Option<&'a str> {
+        str::from_utf8(self.0).ok()
+    }
+}
+
+impl<'a> fmt::Debug for Com<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Com")
+            .field(&String::from_utf8_lossy(self.0))
+            .finish()
+    }
+}
+
+ffi_enum! {
+    /// Unit of the pixel density in a [`Jfif`] segment.
+    pub enum DensityUnits: u8 {
+        /// No units; the density only specifies the pixel aspect ratio.
+        None = 0,
+        DotsPerInch = 1,
+        DotsPerCentimeter = 2,
+    }
+}
+
+/// **APP0** JFIF header.
+#[derive(Clone, Copy)]
+pub struct Jfif<'a> {
+    version: (u8, u8),
+    units: DensityUnits,
+    x_density: u16,
+    y_density: u16,
+    thumbnail_width: u8,
+    thumbnail_height: u8,
+    thumbnail: &'a [u8],
+}
+
+impl<'a> Jfif<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let [major, minor, units, xd0, xd1, yd0, yd1, tw, th, rest @ ..] = data else {
+            return None;
+        };
+        let thumbnail_len = usize::from(*tw) * usize::from(*th) * 3;
+        Some(Self {
+            version: (*major, *minor),
+            units: DensityUnits(*units),
+            x_density: u16::from_be_bytes([*xd0, *xd1]),
+            y_density: u16::from_be_bytes([*yd0, *yd1]),
+            thumbnail_width: *tw,
+            thumbnail_height: *th,
+            thumbnail: rest.get(..thumbnail_len)?,
+        })
+    }
+
+    /// Returns the JFIF version as `(major, minor)`.
+    #[inline]
+    pub fn version(&self) -> (u8, u8) {
+        self.version
+    }
+
+    #[inline]
+    pub fn units(&self) -> DensityUnits {
+        self.units
+    }
+
+    /// Returns the horizontal pixel density.
+    #[inline]
+    pub fn x_density(&self) -> u16 {
+        self.x_density
+    }
+
+    /// Returns the vertical pixel density.
+    #[inline]
+    pub fn y_density(&self) -> u16 {
+        self.y_density
+    }
+
+    #[inline]
+    pub fn thumbnail_width(&self) -> u8 {
+        self.thumbnail_width
+    }
+
+    #[inline]
+    pub fn thumbnail_height(&self) -> u8 {
+        self.thumbnail_height
+    }
+
+    /// Returns the uncompressed thumbnail, stored as packed 8-bit RGB.
+    ///
+    /// Empty if the JFIF header contains no thumbnail.
+    #[inline]
+    pub fn thumbnail(&self) -> &'a [u8] {
+        self.thumbnail
+    }
+}
+
+impl<'a> fmt::Debug for Jfif<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Jfif")
+            .field("version", &self.version)
+            .field("units", &self.units)
+            .field("x_density", &self.x_density)
+            .field("y_density", &self.y_density)
+            .field("thumbnail_width", &self.thumbnail_width)
+            .field("thumbnail_height", &self.thumbnail_height)
+            .finish()
+    }
+}
+
+/// **APP1** EXIF metadata.
+#[derive(Clone, Copy)]
+pub struct Exif<'a> {
+    tiff: &'a [u8],
+}
+
+impl<'a> Exif<'a> {
+    /// Returns the raw EXIF data, which is structured like a TIFF file.
+    #[inline]
+    pub fn data(&self) -> &'a [u8] {
+        self.tiff
+    }
+
+    /// Returns the value of the orientation tag.
+    ///
+    /// Returns [`None`] if the EXIF data is malformed, or does not contain a valid orientation
+    /// tag (1-8).
+    pub fn orientation(&self) -> Option<u8> {
+        const ORIENTATION_TAG: u16 = 0x0112;
+        const TYPE_SHORT: u16 = 3;
+
+        let tiff = self.tiff;
+        let little_endian = match tiff.get(..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        let u16_at = |offset: usize| {
+            let bytes = tiff.get(offset..offset.checked_add(2)?)?.try_into().ok()?;
+            Some(if little_endian {
+                u16::from_le_bytes(bytes)
+            } else {
+                u16::from_be_bytes(bytes)
+            })
+        };
+        let u32_at = |offset: usize| {
+            let bytes = tiff.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+            Some(if little_endian {
+                u32::from_le_bytes(bytes)
+            } else {
+                u32::from_be_bytes(bytes)
+            })
+        };
+
+        if u16_at(2)? != 42 {
+            return None;
+        }
+
+        // Only IFD0 (describing the main image) contains the orientation tag.
+        let ifd0 = usize::try_from(u32_at(4)?).ok()?;
+        let num_entries = usize::from(u16_at(ifd0)?);
+        (0..num_entries).find_map(|i| {
+            let entry = ifd0 + 2 + i * 12;
+            if u16_at(entry)? != ORIENTATION_TAG || u16_at(entry + 2)? != TYPE_SHORT {
+                return None;
+            }
+            let orientation = u16_at(entry + 8)?;
+            (1..=8).contains(&orientation).then_some(orientation as u8)
+        })
+    }
+}
+
+impl<'a> fmt::Debug for Exif<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Exif")
+            .field("len", &self.tiff.len())
+            .field("orientation", &self.orientation())
+            .finish()
+    }
+}
+
+/// **APP1** XMP metadata.
+#[derive(Clone, Copy)]
+pub struct Xmp<'a>(&'a [u8]);
+
+impl<'a> Xmp<'a> {
+    /// Returns the XMP packet, which is an XML document.
+    #[inline]
+    pub fn data(&self) -> &'a [u8] {
+        self.0
+    }
+
+    /// Returns the XMP packet as a string, if it is valid UTF-8.
+    #[inline]
+    pub fn as_str(&self) -> Option<&'a str> {
+        str::from_utf8(self.0).ok()
+    }
+}
+
+impl<'a> fmt::Debug for Xmp<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Xmp").field("len", &self.0.len()).finish()
+    }
+}
+
+/// **APP2** chunk of an ICC color profile.
+///
+/// Since a segment can store at most 64 KiB, large profiles are split across several chunks. Use
+/// [`icc_profile`] to reassemble the complete profile.
+#[derive(Clone, Copy)]
+pub struct IccChunk<'a> {
+    seq_no: u8,
+    num_chunks: u8,
+    data: &'a [u8],
+}
+
+impl<'a> IccChunk<'a> {
+    /// Returns the 1-based sequence number of this chunk.
+    #[inline]
+    pub fn seq_no(&self) -> u8 {
+        self.seq_no
+    }
+
+    /// Returns the total number of chunks the profile is split into.
+    #[inline]
+    pub fn num_chunks(&self) -> u8 {
+        self.num_chunks
+    }
+
+    #[inline]
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl<'a> fmt::Debug for IccChunk<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IccChunk")
+            .field("seq_no", &self.seq_no)
+            .field("num_chunks", &self.num_chunks)
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+ffi_enum! {
+    /// Color transform specified in an [`Adobe`] segment.
+    pub enum AdobeTransform: u8 {
+        /// No transform: RGB for 3-component images, CMYK for 4-component images.
+        Unknown = 0,
+        YCbCr = 1,
+        YCCK = 2,
+    }
+}
+
+/// **APP14** Adobe segment.
+#[derive(Debug, Clone, Copy)]
+pub struct Adobe {
+    version: u16,
+    flags0: u16,
+    flags1: u16,
+    transform: AdobeTransform,
+}
+
+impl Adobe {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let [v0, v1, f00, f01, f10, f11, transform, ..] = data else {
+            return None;
+        };
+        Some(Self {
+            version: u16::from_be_bytes([*v0, *v1]),
+            flags0: u16::from_be_bytes([*f00, *f01]),
+            flags1: u16::from_be_bytes([*f10, *f11]),
+            transform: AdobeTransform(*transform),
+        })
+    }
+
+    #[inline]
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    #[inline]
+    pub fn flags0(&self) -> u16 {
+        self.flags0
+    }
+
+    #[inline]
+    pub fn flags1(&self) -> u16 {
+        self.flags1
+    }
+
+    /// Returns the color transform applied to the image data.
+    #[inline]
+    pub fn transform(&self) -> AdobeTransform {
+        self.transform
+    }
+}
+
 /// **SOF** Start Of Frame
 #[derive(Debug)]
 pub struct Sof<'a> {
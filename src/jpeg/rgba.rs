@@ -0,0 +1,230 @@
+use std::sync::Arc;
+
+use crate::{
+    buffer::{Buffer, BufferType},
+    config::Config,
+    context::Context,
+    display::{Display, DisplayOwner},
+    raw::Rectangle,
+    surface::SurfaceWithImage,
+    vpp::{ColorProperties, ColorStandardType, ProcPipelineParameterBuffer, SourceRange},
+    Entrypoint, PixelFormat, Profile, Result,
+};
+
+use super::{ColorSpace, DecodeOptions, JpegDecoder};
+
+/// A decoded image with tightly packed 8-bit RGBA pixels.
+#[derive(Debug, Clone)]
+pub struct RgbaImage {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// Returns the width of the image in pixels.
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels.
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the number of bytes per row of pixels.
+    ///
+    /// Since rows are tightly packed, this is always `width * 4`.
+    #[inline]
+    pub fn stride(&self) -> usize {
+        self.width as usize * 4
+    }
+
+    /// Returns the pixel data, stored as `r, g, b, a` bytes, row by row.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the pixel data, consuming `self`.
+    #[inline]
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Decodes a baseline JPEG image to RGBA.
+///
+/// This is a convenience function that creates a [`JpegToRgbaPipeline`] for a single image. When
+/// decoding many images, create a [`JpegToRgbaPipeline`] once and reuse it instead.
+///
+/// # Errors
+///
+/// Returns an error if the JPEG is malformed or unsupported, or if VA-API does not support JPEG
+/// decoding or video processing.
+pub fn decode_to_rgba(display: &Display, jpeg: &[u8]) -> Result<RgbaImage> {
+    JpegToRgbaPipeline::new(display)?.decode(jpeg)
+}
+
+/// A reusable pipeline that decodes JPEG images and converts them to RGBA.
+///
+/// The image is decoded by a [`JpegDecoder`], and converted to sRGB RGBA with the video
+/// processor. The result is read back using `vaDeriveImage` if the driver supports it, and
+/// `vaGetImage` otherwise.
+pub struct JpegToRgbaPipeline {
+    d: Arc<DisplayOwner>,
+    decoder: JpegDecoder,
+    vpp_config: Config,
+    /// VPP context and output surface, recreated whenever the output size changes.
+    output: Option<(u32, u32, Context, SurfaceWithImage)>,
+}
+
+impl JpegToRgbaPipeline {
+    /// Creates a new [`JpegToRgbaPipeline`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if VA-API does not support JPEG decoding or video processing.
+    pub fn new(display: &Display) -> Result<Self> {
+        Ok(Self {
+            d: display.d.clone(),
+            decoder: JpegDecoder::new(display)?,
+            vpp_config: Config::new(display, Profile::None, Entrypoint::VideoProc)?,
+            output: None,
+        })
+    }
+
+    /// Returns a reference to the [`JpegDecoder`] used by this pipeline, for configuration.
+    #[inline]
+    pub fn decoder(&mut self) -> &mut JpegDecoder {
+        &mut self.decoder
+    }
+
+    /// Decodes a baseline JPEG image to RGBA.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JPEG is malformed or unsupported, or VA-API returns an error.
+    pub fn decode(&mut self, jpeg: &[u8]) -> Result<RgbaImage> {
+        self.decode_with_options(jpeg, &DecodeOptions::new())
+    }
+
+    /// Decodes a baseline JPEG image to RGBA, using the given [`DecodeOptions`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JPEG is malformed or unsupported, the options are not supported,
+    /// or VA-API returns an error.
+    pub fn decode_with_options(
+        &mut self,
+        jpeg: &[u8],
+        options: &DecodeOptions,
+    ) -> Result<RgbaImage> {
+        let mut decoded = self.decoder.decode_with_options(jpeg, options)?;
+        let width = u32::from(decoded.width());
+        let height = u32::from(decoded.height());
+        let color_space = decoded.info().color_space();
+
+        if !matches!(&self.output, Some((w, h, ..)) if (*w, *h) == (width, height)) {
+            log::debug!("creating {width}x{height} RGBA output surface");
+            let context = Context::new(&self.vpp_config, width, height)?;
+            let surface = SurfaceWithImage::create(&self.d, width, height, PixelFormat::RGBA)?;
+            self.output = Some((width, height, context, surface));
+        }
+        let Some((_, _, context, output)) = &mut self.output else {
+            unreachable!()
+        };
+
+        // The decoded surface can be larger than the image, so only the image region is used.
+        let region = Rectangle::new(0, 0, width as u16, height as u16);
+        let mut pppbuf = ProcPipelineParameterBuffer::new(decoded.surface());
+        pppbuf.set_surface_region(&region);
+        if color_space == ColorSpace::YUV {
+            // JFIF specifies full range BT.601 YCbCr.
+            pppbuf.set_input_color_standard(ColorStandardType::BT601);
+        } else {
+            pppbuf.set_input_color_standard(ColorStandardType::SRGB);
+        }
+        let full_range = ColorProperties::new().with_color_range(SourceRange::FULL);
+        pppbuf.set_input_color_properties(full_range);
+        pppbuf.set_output_color_standard(ColorStandardType::SRGB);
+        pppbuf.set_output_color_properties(full_range);
+        let mut pppbuf = Buffer::new_param(context, BufferType::ProcPipelineParameter, pppbuf)?;
+
+        let mut picture = context.begin_picture(output)?;
+        unsafe {
+            picture.render_picture(&mut pppbuf)?;
+            picture.end_picture()?;
+        }
+        drop(pppbuf);
+
+        let pitch = output.image().raw.pitches[0] as usize;
+        let offset = output.image().raw.offsets[0] as usize;
+        let mapping = output.map_sync()?;
+
+        // Copy the rows, leaving out any padding at the end of each row.
+        let row_len = width as usize * 4;
+        let mut data = Vec::with_capacity(row_len * height as usize);
+        for row in 0..height as usize {
+            let start = offset + row * pitch;
+            data.extend_from_slice(&mapping[start..start + row_len]);
+        }
+
+        Ok(RgbaImage {
+            width,
+            height,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::run_test;
+
+    use super::*;
+
+    #[test]
+    fn jpeg_to_rgba() {
+        run_test(|display| {
+            let mut pipeline = match JpegToRgbaPipeline::new(display) {
+                Ok(pipeline) => pipeline,
+                Err(e) => {
+                    log::warn!("skipping test; JPEG decoding or VPP is not supported: {e}");
+                    return;
+                }
+            };
+
+            let jpeg = std::fs::read("src/jpeg/test-images/restarts.jpg").unwrap();
+            let image = pipeline.decode(&jpeg).unwrap();
+            assert_eq!((image.width(), image.height()), (33, 33));
+            assert_eq!(image.stride(), 33 * 4);
+            assert_eq!(image.data().len(), 33 * 33 * 4);
+        });
+    }
+
+    #[test]
+    fn dht_between_scans() {
+        run_test(|display| {
+            let mut pipeline = match JpegToRgbaPipeline::new(display) {
+                Ok(pipeline) => pipeline,
+                Err(e) => {
+                    log::warn!("skipping test; JPEG decoding or VPP is not supported: {e}");
+                    return;
+                }
+            };
+
+            // Three non-interleaved scans of a flat gray 8x8 image, each preceded by a DHT segment.
+            let jpeg = std::fs::read("src/jpeg/test-images/dht-between-scans.jpg").unwrap();
+            let image = pipeline.decode(&jpeg).unwrap();
+            assert_eq!((image.width(), image.height()), (8, 8));
+            for pixel in image.data().chunks(4) {
+                for &channel in &pixel[..3] {
+                    assert!(channel.abs_diff(128) <= 2, "unexpected pixel {pixel:?}");
+                }
+            }
+        });
+    }
+}
@@ -2,7 +2,15 @@ use std::{error::Error, fmt::Write, fs};
 
 use expect_test::{expect_file, ExpectFile};
 
-use super::parser::JpegParser;
+use crate::{surface::RTFormat, PixelFormat, Rotation};
+
+use super::{
+    parser::{
+        self, AdobeTransform, DensityUnits, JpegError, JpegErrorKind, JpegParser, SegmentKind,
+    },
+    restart_boundaries, split_scan_data, ColorSpace, DecodeOptions, HuffmanSlots, JpegInfo,
+    JpegSupport, PictureParams,
+};
 
 fn check(filename: &str, expect: ExpectFile) {
     let bytes = std::fs::read(format!("src/jpeg/test-images/{filename}")).unwrap();
@@ -37,3 +45,421 @@ fn parser_tests() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[test]
+fn probe() {
+    fn probe(filename: &str) -> (JpegInfo, JpegSupport) {
+        let bytes = std::fs::read(format!("src/jpeg/test-images/{filename}")).unwrap();
+        JpegInfo::probe(&bytes).unwrap()
+    }
+
+    let (info, support) = probe("mjpeg.jpg");
+    assert_eq!(support, JpegSupport::Supported);
+    assert_eq!((info.width(), info.height()), (960, 720));
+    assert_eq!(info.num_components(), 3);
+    assert_eq!(info.sampling_factors(), &[(2, 1), (1, 1), (1, 1)]);
+    assert_eq!(info.color_space(), ColorSpace::YUV);
+    assert_eq!(info.rt_format(), Some(RTFormat::YUV422));
+    assert_eq!(info.pixel_format(), Some(PixelFormat::YUV422H));
+
+    let (info, support) = probe("grayscale_16x24_sampling2x2.jpg");
+    assert_eq!(support, JpegSupport::Supported);
+    assert_eq!(info.sampling_factors(), &[(2, 2)]);
+    assert_eq!(info.pixel_format(), Some(PixelFormat::Y800));
+
+    let (info, support) = probe("rgb.jpg");
+    assert_eq!(support, JpegSupport::Supported);
+    assert_eq!(info.color_space(), ColorSpace::RGB);
+    assert_eq!(info.rt_format(), Some(RTFormat::RGBP));
+
+    let (info, _) = probe("restarts.jpg");
+    assert_eq!(info.color_space(), ColorSpace::YUV);
+    assert_eq!(info.pixel_format(), Some(PixelFormat::YUV444P));
+
+    let (_, support) = probe("progressive3.jpg");
+    assert_eq!(support, JpegSupport::Progressive);
+
+    let (info, support) = probe("16bit-qtables.jpg");
+    assert_eq!(support, JpegSupport::Extended { precision: 8 });
+    assert_eq!(info.precision(), 8);
+
+    assert!(JpegInfo::probe(&[0xff, 0xd8]).is_err());
+}
+
+#[test]
+fn split_scan() {
+    // Two restart intervals, with a stuffed `ff 00` and a fill byte before the second RST marker.
+    let data = [
+        0x12, 0xff, 0x00, 0x34, 0xff, 0xd0, 0x56, 0x78, 0xff, 0xff, 0xd1, 0x9a,
+    ];
+    assert_eq!(restart_boundaries(&data), [6, 11]);
+
+    assert_eq!(split_scan_data(&data, None), [&data[..]]);
+    assert_eq!(split_scan_data(&data, Some(100)), [&data[..]]);
+    assert_eq!(split_scan_data(&data, Some(6)), [&data[..6], &data[6..]]);
+    assert_eq!(split_scan_data(&data, Some(11)), [&data[..11], &data[11..]]);
+    // Intervals larger than the limit can't be split further.
+    assert_eq!(
+        split_scan_data(&data, Some(1)),
+        [&data[..6], &data[6..11], &data[11..]]
+    );
+    assert_eq!(split_scan_data(&[], Some(1)), [&[][..]]);
+}
+
+#[test]
+fn huffman_slots() {
+    // Each scan is preceded by a DHT segment. The second one replaces the tables used by the first
+    // scan, so they are moved to the free slot 1; the third one repeats the second.
+    let bytes = std::fs::read("src/jpeg/test-images/dht-between-scans.jpg").unwrap();
+    let mut slots = HuffmanSlots::default();
+    let mut scan_slots = Vec::new();
+    let mut parser = JpegParser::new(&bytes);
+    while let Some(segment) = parser.next_segment().unwrap() {
+        match segment.kind {
+            SegmentKind::Dht(dht) => {
+                for table in dht.tables() {
+                    assert!(slots.define(table.Tc(), table.Th(), table.Li(), table.Vij()));
+                }
+            }
+            SegmentKind::Sos(sos) => {
+                let component = &sos.components()[0];
+                scan_slots.push((
+                    slots.resolve(0, component.Tdj()),
+                    slots.resolve(1, component.Taj()),
+                ));
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(scan_slots, [(0, 0), (1, 1), (1, 1)]);
+    assert!(slots.buffer().is_some());
+
+    // Both slots are in use, so a third distinct table can't be loaded.
+    let mut li = [0; 16];
+    li[2] = 1;
+    assert!(!slots.define(0, 0, &li, &[0]));
+    assert!(!slots.define(0, 1, &li, &[0]));
+
+    // Before any scan, tables can be redefined freely.
+    let mut slots = HuffmanSlots::default();
+    assert!(slots.buffer().is_none());
+    assert!(slots.define(0, 0, &li, &[0]));
+    assert!(slots.define(0, 0, &li, &[1]));
+    assert_eq!(slots.resolve(0, 0), 0);
+    assert_eq!(slots.resolve(0, 1), 1);
+}
+
+/// Builds a JPEG file consisting of the given marker segments, framed by SOI and EOI.
+fn jpeg_with_segments(segments: &[(u8, &[u8])]) -> Vec<u8> {
+    let mut jpeg = vec![0xFF, 0xD8];
+    for (marker, payload) in segments {
+        jpeg.extend_from_slice(&[0xFF, *marker]);
+        jpeg.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        jpeg.extend_from_slice(payload);
+    }
+    jpeg.extend_from_slice(&[0xFF, 0xD9]);
+    jpeg
+}
+
+fn exif_segment_orientation(app1: &[u8]) -> Option<u8> {
+    let jpeg = jpeg_with_segments(&[(0xE1, app1)]);
+    let mut parser = JpegParser::new(&jpeg);
+    parser.next_segment().unwrap();
+    match parser.next_segment().unwrap().unwrap().kind {
+        SegmentKind::Exif(exif) => exif.orientation(),
+        _ => None,
+    }
+}
+
+#[test]
+fn exif_orientation() {
+    #[rustfmt::skip]
+    let little_endian = [
+        b'E', b'x', b'i', b'f', 0, 0,
+        b'I', b'I', 42, 0, 8, 0, 0, 0,
+        // IFD0 with 2 entries: ImageWidth and Orientation.
+        2, 0,
+        0x00, 0x01, 3, 0, 1, 0, 0, 0, 64, 0, 0, 0,
+        0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0,
+        0, 0, 0, 0,
+    ];
+    assert_eq!(exif_segment_orientation(&little_endian), Some(6));
+
+    #[rustfmt::skip]
+    let big_endian = [
+        b'E', b'x', b'i', b'f', 0, 0,
+        b'M', b'M', 0, 42, 0, 0, 0, 8,
+        0, 1,
+        0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 8, 0, 0,
+        0, 0, 0, 0,
+    ];
+    assert_eq!(exif_segment_orientation(&big_endian), Some(8));
+
+    // Truncated IFD.
+    assert_eq!(exif_segment_orientation(&little_endian[..30]), None);
+    assert_eq!(
+        exif_segment_orientation(b"http://ns.adobe.com/xap/1.0/\0"),
+        None
+    );
+}
+
+#[test]
+fn app_segments() {
+    let jpeg = jpeg_with_segments(&[
+        (0xE0, b"JFIF\0\x01\x02\x01\0\x48\0\x48\0\0"),
+        (0xE1, b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta/>"),
+        (0xEE, b"Adobe\0\x64\0\0\0\0\x01"),
+        (0xFE, b"comment"),
+        (0xE0, b"JFIF\0\x01"),
+    ]);
+    let mut parser = JpegParser::new(&jpeg);
+    parser.next_segment().unwrap();
+
+    let SegmentKind::Jfif(jfif) = parser.next_segment().unwrap().unwrap().kind else {
+        panic!("expected JFIF segment");
+    };
+    assert_eq!(jfif.version(), (1, 2));
+    assert_eq!(jfif.units(), DensityUnits::DotsPerInch);
+    assert_eq!((jfif.x_density(), jfif.y_density()), (72, 72));
+    assert!(jfif.thumbnail().is_empty());
+
+    let SegmentKind::Xmp(xmp) = parser.next_segment().unwrap().unwrap().kind else {
+        panic!("expected XMP segment");
+    };
+    assert_eq!(xmp.as_str(), Some("<x:xmpmeta/>"));
+
+    let SegmentKind::Adobe(adobe) = parser.next_segment().unwrap().unwrap().kind else {
+        panic!("expected Adobe segment");
+    };
+    assert_eq!(adobe.version(), 100);
+    assert_eq!(adobe.transform(), AdobeTransform::YCbCr);
+
+    let SegmentKind::Com(com) = parser.next_segment().unwrap().unwrap().kind else {
+        panic!("expected COM segment");
+    };
+    assert_eq!(com.as_str(), Some("comment"));
+
+    // Truncated JFIF header.
+    assert!(matches!(
+        parser.next_segment().unwrap().unwrap().kind,
+        SegmentKind::Other { marker: 0xE0, .. }
+    ));
+}
+
+#[test]
+fn icc_profile() {
+    // Chunks may appear in any order.
+    let jpeg = jpeg_with_segments(&[
+        (0xE2, b"ICC_PROFILE\0\x02\x02world"),
+        (0xE2, b"ICC_PROFILE\0\x01\x02hello "),
+    ]);
+    assert_eq!(
+        parser::icc_profile(&jpeg).unwrap().as_deref(),
+        Some(&b"hello world"[..])
+    );
+
+    let missing = jpeg_with_segments(&[(0xE2, b"ICC_PROFILE\0\x01\x02hello ")]);
+    let err = parser::icc_profile(&missing).unwrap_err();
+    let err = err.as_jpeg().unwrap();
+    assert_eq!(
+        err.kind(),
+        &JpegErrorKind::IccChunkCount {
+            expected: 2,
+            found: 1
+        }
+    );
+    assert_eq!(err.segment(), Some(0xE2));
+    assert_eq!(err.offset(), 2);
+
+    let duplicate = jpeg_with_segments(&[
+        (0xE2, b"ICC_PROFILE\0\x01\x02hello "),
+        (0xE2, b"ICC_PROFILE\0\x01\x02hello "),
+    ]);
+    let err = parser::icc_profile(&duplicate).unwrap_err();
+    let err = err.as_jpeg().unwrap();
+    assert_eq!(err.kind(), &JpegErrorKind::MissingIccChunk(2));
+    assert_eq!(err.segment(), Some(0xE2));
+    assert_eq!(err.offset(), 26);
+
+    assert_eq!(parser::icc_profile(&jpeg_with_segments(&[])).unwrap(), None);
+}
+
+#[test]
+fn decode_options() {
+    let bytes = std::fs::read("src/jpeg/test-images/mjpeg.jpg").unwrap();
+    let (info, _) = JpegInfo::probe(&bytes).unwrap();
+    let formats = info.formats().unwrap();
+    // All rotations, and cropping.
+    let all_features = 0b1_1111;
+
+    let (size, _) = DecodeOptions::new()
+        .resolve(&info, formats, all_features)
+        .unwrap();
+    assert_eq!(size, (960, 720));
+
+    let (size, (_, pixel_format)) = DecodeOptions::new()
+        .with_rotation(Rotation::R90)
+        .with_crop(10, 20, 100, 50)
+        .resolve(&info, formats, all_features)
+        .unwrap();
+    assert_eq!(size, (50, 100));
+    assert_eq!(pixel_format, PixelFormat::YUV422V);
+
+    assert!(DecodeOptions::new()
+        .with_rotation(Rotation::R90)
+        .resolve(&info, formats, 0b0001)
+        .is_err());
+    assert!(DecodeOptions::new()
+        .with_crop(900, 0, 100, 100)
+        .resolve(&info, formats, all_features)
+        .is_err());
+    // Cropping is rejected if the implementation doesn't report support for it.
+    assert!(DecodeOptions::new()
+        .with_crop(0, 0, 100, 100)
+        .resolve(&info, formats, 0b1111)
+        .is_err());
+}
+
+#[test]
+fn parser_errors() {
+    // SOI followed by a DQT segment that is cut off.
+    let mut parser = JpegParser::new(&[0xFF, 0xD8, 0xFF, 0xDB, 0x00, 0x43, 0x00]);
+    parser.next_segment().unwrap();
+    let err = parser.next_segment().unwrap_err();
+    assert_eq!(err.kind(), &JpegErrorKind::UnexpectedEof);
+    assert_eq!(err.segment(), Some(0xDB));
+    assert_eq!(err.offset(), 6);
+
+    let mut parser = JpegParser::new(&[0xFF, 0xD8, 0xFF, 0xC0, 0x00, 0x01]);
+    parser.next_segment().unwrap();
+    let err = parser.next_segment().unwrap_err();
+    assert_eq!(err.kind(), &JpegErrorKind::InvalidLength(1));
+    assert_eq!(err.offset(), 4);
+    assert_eq!(
+        err.to_string(),
+        "invalid segment length 1 at offset 0x4 in segment FFC0"
+    );
+
+    let err = JpegInfo::new(&[0xFF, 0xD8, 0xFF, 0x00]).unwrap_err();
+    assert_eq!(
+        err.as_jpeg().map(|e| e.kind()),
+        Some(&JpegErrorKind::InvalidMarker(0x00))
+    );
+}
+
+#[test]
+fn probe_errors() {
+    fn probe_err(jpeg: &[u8]) -> JpegError {
+        JpegInfo::probe(jpeg)
+            .unwrap_err()
+            .as_jpeg()
+            .unwrap()
+            .clone()
+    }
+
+    let err = probe_err(&jpeg_with_segments(&[]));
+    assert_eq!(err.kind(), &JpegErrorKind::MissingSegment(0xC0));
+    assert_eq!(err.offset(), 4);
+
+    let err = probe_err(&jpeg_with_segments(&[(0xC0, &[8, 0, 16, 0, 16, 0])]));
+    assert_eq!(err.kind(), &JpegErrorKind::NoComponents);
+    assert_eq!(err.segment(), Some(0xC0));
+    assert_eq!(err.offset(), 2);
+
+    let err = probe_err(&jpeg_with_segments(&[(
+        0xC0,
+        &[8, 0, 16, 0, 16, 1, 1, 0x51, 0],
+    )]));
+    assert_eq!(
+        err.kind(),
+        &JpegErrorKind::InvalidSamplingFactors {
+            component: 1,
+            h: 5,
+            v: 1
+        }
+    );
+    assert_eq!(err.segment(), Some(0xC0));
+
+    let err = probe_err(&jpeg_with_segments(&[(
+        0xC0,
+        &[12, 0, 16, 0, 16, 1, 1, 0x11, 0],
+    )]));
+    assert_eq!(err.kind(), &JpegErrorKind::InvalidPrecision(12));
+    assert_eq!(err.segment(), Some(0xC0));
+}
+
+#[test]
+fn picture_params() {
+    let jpeg = fs::read("src/jpeg/test-images/restarts.jpg").unwrap();
+    let (info, _) = JpegInfo::probe(&jpeg).unwrap();
+    let options = DecodeOptions::new();
+    let params = PictureParams::parse(&jpeg, info.color_space(), &options, None).unwrap();
+    assert_eq!(params.slices.len(), 1);
+    let split = PictureParams::parse(&jpeg, info.color_space(), &options, Some(64)).unwrap();
+    assert!(split.slices.len() > 1);
+
+    // Scans may only select Huffman tables 0 and 1.
+    let mut jpeg = fs::read("src/jpeg/test-images/mjpeg.jpg").unwrap();
+    let sos = jpeg.windows(2).position(|w| w == [0xFF, 0xDA]).unwrap();
+    jpeg[sos + 6] = 0x22;
+    let (info, _) = JpegInfo::probe(&jpeg).unwrap();
+    assert!(PictureParams::parse(&jpeg, info.color_space(), &options, None).is_err());
+}
+
+/// Feeds truncated and randomly corrupted versions of the test images to the parser, which must
+/// return errors instead of panicking.
+#[test]
+fn parser_no_panic() {
+    fn parse_all(jpeg: &[u8]) {
+        let mut parser = JpegParser::new(jpeg);
+        while let Ok(Some(segment)) = parser.next_segment() {
+            let _ = format!("{:?}", segment.kind);
+        }
+        parser::icc_profile(jpeg).ok();
+
+        // Everything `decode_picture` does before handing the buffers to the driver.
+        let Ok((info, _)) = JpegInfo::probe(jpeg) else {
+            return;
+        };
+        let options = DecodeOptions::new()
+            .with_exif_orientation(&info)
+            .with_crop(8, 8, 16, 16);
+        if let Some(formats) = info.formats() {
+            options.resolve(&info, formats, 0b1_1111).ok();
+        }
+        for max_slice_data_size in [None, Some(64)] {
+            PictureParams::parse(jpeg, info.color_space(), &options, max_slice_data_size).ok();
+        }
+    }
+
+    // xorshift64, for reproducible pseudo-random mutations.
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut rand = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as usize
+    };
+
+    for entry in fs::read_dir("src/jpeg/test-images").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().unwrap() != "jpg" {
+            continue;
+        }
+        // The headers are at the start of the file, so that's where mutations are interesting.
+        let mut bytes = fs::read(path).unwrap();
+        bytes.truncate(4096);
+
+        for len in 0..bytes.len() {
+            parse_all(&bytes[..len]);
+        }
+        for _ in 0..500 {
+            let mut mutated = bytes.clone();
+            for _ in 0..1 + rand() % 4 {
+                let index = rand() % mutated.len();
+                mutated[index] = rand() as u8;
+            }
+            parse_all(&mutated);
+        }
+    }
+}
@@ -18,6 +18,9 @@ pub mod config;
 pub mod context;
 pub mod display;
 pub mod error;
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[cfg(feature = "async")]
+pub mod future;
 pub mod image;
 pub mod jpeg;
 pub mod subpicture;
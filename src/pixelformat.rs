@@ -21,6 +21,40 @@ impl PixelFormat {
     /// Planar YUV 4:2:0 pixel format, with U and V swapped compared to `NV12`.
     pub const NV21: Self = f(b"NV21");
 
+    /// Planar YUV 4:2:0 with 3 separate planes, in Y, U, V order.
+    pub const I420: Self = f(b"I420");
+
+    /// Planar YUV 4:2:0 with 3 separate planes, in Y, V, U order.
+    pub const YV12: Self = f(b"YV12");
+
+    /// Planar YUV 4:2:0 with 10-bit samples.
+    ///
+    /// Laid out like [`NV12`](Self::NV12), but every sample is stored in the upper 10 bits of a
+    /// 16-bit little-endian word. This is the typical format of decoded HDR10 video.
+    pub const P010: Self = f(b"P010");
+
+    /// Planar YUV 4:2:2 with 3 separate planes, and horizontally subsampled U and V planes.
+    ///
+    /// This is the typical output format of 4:2:2 JPEG decoding.
+    pub const YUV422H: Self = f(b"422H");
+
+    /// Planar YUV 4:2:2 (more commonly called 4:4:0) with 3 separate planes, and vertically
+    /// subsampled U and V planes.
+    pub const YUV422V: Self = f(b"422V");
+
+    /// Planar YUV 4:4:4 with 3 separate planes.
+    pub const YUV444P: Self = f(b"444P");
+
+    /// Planar YUV 4:1:1 with 3 separate planes, and U and V planes subsampled by a factor of 4
+    /// horizontally.
+    pub const YUV411P: Self = f(b"411P");
+
+    /// Grayscale with 8-bit samples (a single Y plane).
+    pub const Y800: Self = f(b"Y800");
+
+    /// Planar 8-bit RGB, with separate R, G and B planes.
+    pub const RGBP: Self = f(b"RGBP");
+
     /// Interleaved YUV 4:2:2, stored in memory as `yyyyyyyy uuuuuuuu YYYYYYYY vvvvvvvv`.
     ///
     /// `uuuuuuuu` and `vvvvvvvv` are shared by 2 horizontally neighboring pixels.
@@ -76,12 +110,83 @@ impl PixelFormat {
     /// Returns [`None`] when `self` is an unknown or unhandled [`PixelFormat`].
     pub fn to_rtformat(self) -> Option<RTFormat> {
         Some(match self {
-            Self::NV12 | Self::NV21 => RTFormat::YUV420,
-            Self::YUY2 | Self::YUYV | Self::UYVY => RTFormat::YUV422,
+            Self::NV12 | Self::NV21 | Self::I420 | Self::YV12 => RTFormat::YUV420,
+            Self::P010 => RTFormat::YUV420_10,
+            Self::YUY2 | Self::YUYV | Self::UYVY | Self::YUV422H | Self::YUV422V => {
+                RTFormat::YUV422
+            }
+            Self::YUV444P => RTFormat::YUV444,
+            Self::YUV411P => RTFormat::YUV411,
+            Self::Y800 => RTFormat::YUV400,
+            Self::RGBP => RTFormat::RGBP,
             Self::RGBA | Self::RGBX | Self::ARGB | Self::BGRA | Self::BGRX => RTFormat::RGB32,
             _ => return None,
         })
     }
+
+    /// Returns the size of every plane of a `width`x`height` image in this format.
+    ///
+    /// An element is the smallest unit a row of the plane is made of. This is a single sample for
+    /// planar formats, a U/V sample pair for the chroma plane of semi-planar formats like
+    /// [`PixelFormat::NV12`], a whole pixel for packed RGB formats, and a group of 2 pixels for
+    /// packed YUV 4:2:2 formats.
+    ///
+    /// Returns [`None`] if the plane layout of the format is unknown.
+    pub(crate) fn plane_sizes(self, width: u32, height: u32) -> Option<Vec<PlaneSize>> {
+        let plane = |width, height, bytes_per_element| PlaneSize {
+            width,
+            height,
+            bytes_per_element,
+        };
+        let (half_width, half_height) = (width.div_ceil(2), height.div_ceil(2));
+        Some(match self {
+            Self::NV12 | Self::NV21 => {
+                vec![plane(width, height, 1), plane(half_width, half_height, 2)]
+            }
+            Self::P010 => vec![plane(width, height, 2), plane(half_width, half_height, 4)],
+            Self::I420 | Self::YV12 => vec![
+                plane(width, height, 1),
+                plane(half_width, half_height, 1),
+                plane(half_width, half_height, 1),
+            ],
+            Self::YUV422H => vec![
+                plane(width, height, 1),
+                plane(half_width, height, 1),
+                plane(half_width, height, 1),
+            ],
+            Self::YUV422V => vec![
+                plane(width, height, 1),
+                plane(width, half_height, 1),
+                plane(width, half_height, 1),
+            ],
+            Self::YUV444P | Self::RGBP => vec![plane(width, height, 1); 3],
+            Self::YUV411P => {
+                let quarter_width = width.div_ceil(4);
+                vec![
+                    plane(width, height, 1),
+                    plane(quarter_width, height, 1),
+                    plane(quarter_width, height, 1),
+                ]
+            }
+            Self::Y800 => vec![plane(width, height, 1)],
+            Self::YUY2 | Self::YUYV | Self::UYVY => vec![plane(half_width, height, 4)],
+            Self::RGBA | Self::RGBX | Self::ARGB | Self::BGRA | Self::BGRX => {
+                vec![plane(width, height, 4)]
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// The size of one plane of an image, as returned by [`PixelFormat::plane_sizes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PlaneSize {
+    /// Number of elements in each row.
+    pub width: u32,
+    /// Number of rows.
+    pub height: u32,
+    /// Size of an element in bytes.
+    pub bytes_per_element: u32,
 }
 
 const fn f(fourcc: &[u8; 4]) -> PixelFormat {
@@ -10,6 +10,7 @@ pub const VA_PADDING_MEDIUM: usize = 8;
 pub const VA_PADDING_HIGH: usize = 16;
 pub const VA_PADDING_LARGE: usize = 32;
 pub const VA_TIMEOUT_INFINITE: u64 = 0xFFFFFFFFFFFFFFFF;
+pub const VA_ATTRIB_NOT_SUPPORTED: u32 = 0x80000000;
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
@@ -20,6 +21,63 @@ pub struct Rectangle {
     height: u16,
 }
 
+impl Rectangle {
+    pub fn new(x: i16, y: i16, width: u16, height: u16) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[inline]
+    pub fn x(&self) -> i16 {
+        self.x
+    }
+
+    #[inline]
+    pub fn y(&self) -> i16 {
+        self.y
+    }
+
+    #[inline]
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+}
+
+/// `VADRMFormatModifierList`, the value of a `DRMFormatModifiers` surface attribute.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct VADRMFormatModifierList {
+    pub num_modifiers: u32,
+    pub modifiers: *mut u64,
+}
+
+/// `VASurfaceAttribExternalBuffers`, the external buffer descriptor used with
+/// `VA_SURFACE_ATTRIB_MEM_TYPE_USER_PTR`.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct VASurfaceAttribExternalBuffers {
+    pub pixel_format: u32,
+    pub width: u32,
+    pub height: u32,
+    pub data_size: u32,
+    pub num_planes: u32,
+    pub pitches: [u32; 4],
+    pub offsets: [u32; 4],
+    pub buffers: *mut usize, // uintptr_t *
+    pub num_buffers: u32,
+    pub flags: u32,
+    pub private_data: *mut c_void,
+}
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct VAProcessingRateParameterEnc {
@@ -3,14 +3,17 @@
 #[cfg_attr(docsrs, doc(cfg(target_os = "linux")))]
 #[cfg(target_os = "linux")]
 pub mod drm;
+mod user;
 
 use core::fmt;
 use std::{
     ffi::{c_int, c_uint, c_void},
     mem::MaybeUninit,
     ops::{Deref, DerefMut},
+    ptr, slice,
     sync::Arc,
-    time::Instant,
+    thread,
+    time::{Duration, Instant},
     vec,
 };
 
@@ -21,10 +24,15 @@ use crate::{
     error::VAError,
     image::{Image, ImageFormat},
     pixelformat::PixelFormat,
-    raw::{VAGenericFunc, VASurfaceID, VA_PADDING_LOW},
+    raw::{
+        VADRMFormatModifierList, VAGenericFunc, VASurfaceAttribExternalBuffers, VASurfaceID,
+        VA_PADDING_LOW,
+    },
     Error, Result,
 };
 
+pub use user::{UserMemoryLayout, UserSurface};
+
 bitflags! {
     /// Flags for configuring how a [`Surface`] should be exported.
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -103,23 +111,110 @@ impl SurfaceAttrib {
         self.value
     }
 
+    /// Creates a settable attribute whose value is a pointer.
+    ///
+    /// The pointer is passed to the driver as-is, and has to stay valid until the [`Surface`] has
+    /// been created.
+    pub(crate) fn from_pointer(ty: SurfaceAttribType, ptr: *mut c_void) -> Self {
+        Self {
+            type_: ty,
+            flags: SurfaceAttribFlags::SETTABLE,
+            value: GenericValue::pointer(ptr),
+        }
+    }
+
+    /// Decodes the value of this attribute.
+    ///
+    /// Returns [`None`] for unknown attribute types, and for `DRMFormatModifiers` and
+    /// `ExternalBufferDescriptor` attributes: their value points to memory that may no longer be
+    /// valid. [`SurfaceAttributes::enums`] contains copies of their values instead.
     pub fn as_enum(&self) -> Option<SurfaceAttribEnum> {
         Some(match self.type_ {
             SurfaceAttribType::PixelFormat => SurfaceAttribEnum::PixelFormat(
                 PixelFormat::from_u32_le(self.raw_value().as_int()? as u32),
             ),
+            SurfaceAttribType::MinWidth => {
+                SurfaceAttribEnum::MinWidth(self.raw_value().as_int()? as u32)
+            }
+            SurfaceAttribType::MaxWidth => {
+                SurfaceAttribEnum::MaxWidth(self.raw_value().as_int()? as u32)
+            }
+            SurfaceAttribType::MinHeight => {
+                SurfaceAttribEnum::MinHeight(self.raw_value().as_int()? as u32)
+            }
+            SurfaceAttribType::MaxHeight => {
+                SurfaceAttribEnum::MaxHeight(self.raw_value().as_int()? as u32)
+            }
             SurfaceAttribType::MemoryType => SurfaceAttribEnum::MemoryType(
                 SurfaceAttribMemoryType::from_bits_truncate(self.raw_value().as_int()? as u32),
             ),
+            SurfaceAttribType::UsageHint => SurfaceAttribEnum::UsageHint(
+                SurfaceUsageHint::from_bits_truncate(self.raw_value().as_int()? as u32),
+            ),
+            SurfaceAttribType::AlignmentSize => {
+                // Bits 0-3 contain log2 of the width alignment, bits 4-7 that of the height.
+                let value = self.raw_value().as_int()? as u32;
+                SurfaceAttribEnum::AlignmentSize {
+                    width: 1 << (value & 0xf),
+                    height: 1 << ((value >> 4) & 0xf),
+                }
+            }
             _ => return None,
         })
     }
+
+    /// Like [`SurfaceAttrib::as_enum`], but also copies the data that the value of a
+    /// `DRMFormatModifiers` or `ExternalBufferDescriptor` attribute points to.
+    ///
+    /// # Safety
+    ///
+    /// The value of those attributes must be null, or point to a valid [`VADRMFormatModifierList`]
+    /// or [`VASurfaceAttribExternalBuffers`], respectively, including the arrays they refer to.
+    pub(crate) unsafe fn copy_value(&self) -> Option<SurfaceAttribEnum> {
+        match self.type_ {
+            SurfaceAttribType::DRMFormatModifiers => {
+                let list = self.raw_value().as_pointer()?;
+                let list = list.cast::<VADRMFormatModifierList>().as_ref()?;
+                let modifiers = if list.modifiers.is_null() {
+                    Vec::new()
+                } else {
+                    slice::from_raw_parts(list.modifiers, list.num_modifiers as usize).to_vec()
+                };
+                Some(SurfaceAttribEnum::DRMFormatModifiers(modifiers))
+            }
+            SurfaceAttribType::ExternalBufferDescriptor => {
+                let desc = self.raw_value().as_pointer()?;
+                let desc = desc.cast::<VASurfaceAttribExternalBuffers>().as_ref()?;
+                let buffers = if desc.buffers.is_null() {
+                    Vec::new()
+                } else {
+                    slice::from_raw_parts(desc.buffers, desc.num_buffers as usize).to_vec()
+                };
+                Some(SurfaceAttribEnum::ExternalBufferDescriptor(
+                    ExternalBufferDescriptor {
+                        pixel_format: PixelFormat::from_u32_le(desc.pixel_format),
+                        width: desc.width,
+                        height: desc.height,
+                        data_size: desc.data_size,
+                        num_planes: desc.num_planes.min(4),
+                        pitches: desc.pitches,
+                        offsets: desc.offsets,
+                        buffers,
+                        flags: desc.flags,
+                    },
+                ))
+            }
+            _ => self.as_enum(),
+        }
+    }
 }
 
 /// Collection of supported [`SurfaceAttrib`]s.
 #[derive(Clone)]
 pub struct SurfaceAttributes {
     pub(crate) vec: Vec<SurfaceAttrib>,
+    /// Decoded when the attributes are queried, while the pointers in them are still valid.
+    pub(crate) enums: Vec<SurfaceAttribEnum>,
 }
 
 impl SurfaceAttributes {
@@ -142,6 +237,32 @@ impl SurfaceAttributes {
             _ => None,
         })
     }
+
+    /// Returns the decoded values of all attributes of known type.
+    ///
+    /// Unlike [`SurfaceAttrib::as_enum`], this includes `DRMFormatModifiers` and
+    /// `ExternalBufferDescriptor` attributes, whose data was copied out of driver memory when the
+    /// attributes were queried.
+    #[inline]
+    pub fn enums(&self) -> &[SurfaceAttribEnum] {
+        &self.enums
+    }
+
+    /// Returns the list of supported DRM format modifiers.
+    ///
+    /// libva defines the `DRMFormatModifiers` attribute as write-only, so most drivers do not
+    /// report it, and this is almost always empty. An empty slice does not mean that DRM format
+    /// modifiers are unsupported; [`Surface::with_drm_format_modifiers`] may still succeed.
+    #[inline]
+    pub fn drm_format_modifiers(&self) -> &[u64] {
+        self.enums
+            .iter()
+            .find_map(|attrib| match attrib {
+                SurfaceAttribEnum::DRMFormatModifiers(modifiers) => Some(&modifiers[..]),
+                _ => None,
+            })
+            .unwrap_or(&[])
+    }
 }
 
 impl IntoIterator for SurfaceAttributes {
@@ -200,9 +321,13 @@ ffi_enum! {
     }
 }
 
-#[allow(dead_code)]
+/// A range of macroblocks that could not be decoded correctly.
+///
+/// Returned by [`Surface::decode_errors`].
+#[derive(Clone, Copy)]
 #[repr(C)]
 pub struct SurfaceDecodeMBErrors {
+    /// -1 marks the end of the list returned by the driver.
     status: i32,
     start_mb: u32,
     end_mb: u32,
@@ -211,6 +336,61 @@ pub struct SurfaceDecodeMBErrors {
     va_reserved: [u32; VA_PADDING_LOW - 1],
 }
 
+/// Copies the list of macroblock errors returned by `vaQuerySurfaceError`.
+///
+/// # Safety
+///
+/// `list` must be null, or point to an array of [`SurfaceDecodeMBErrors`] that is terminated by
+/// an entry with a status of -1.
+unsafe fn copy_decode_errors(list: *const SurfaceDecodeMBErrors) -> Vec<SurfaceDecodeMBErrors> {
+    let mut errors = Vec::new();
+    let mut entry = list;
+    while !entry.is_null() && (*entry).status != -1 {
+        errors.push(*entry);
+        entry = entry.add(1);
+    }
+    errors
+}
+
+impl fmt::Debug for SurfaceDecodeMBErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SurfaceDecodeMBErrors")
+            .field("start_mb", &self.start_mb)
+            .field("end_mb", &self.end_mb)
+            .field("decode_error_type", &self.decode_error_type)
+            .field("num_mb", &self.num_mb)
+            .finish()
+    }
+}
+
+impl SurfaceDecodeMBErrors {
+    /// Returns the index of the first affected macroblock, in raster scan order.
+    #[inline]
+    pub fn start_mb(&self) -> u32 {
+        self.start_mb
+    }
+
+    /// Returns the index of the last affected macroblock (inclusive), in raster scan order.
+    #[inline]
+    pub fn end_mb(&self) -> u32 {
+        self.end_mb
+    }
+
+    /// Returns the kind of error affecting the macroblocks.
+    #[inline]
+    pub fn error_type(&self) -> DecodeErrorType {
+        self.decode_error_type
+    }
+
+    /// Returns the number of macroblocks with errors in this range.
+    ///
+    /// Not all drivers fill this in, in which case it is 0.
+    #[inline]
+    pub fn num_mb(&self) -> u32 {
+        self.num_mb
+    }
+}
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 union VAGenericValueUnion {
@@ -243,6 +423,13 @@ impl GenericValue {
         }
     }
 
+    pub(crate) fn pointer(p: *mut c_void) -> Self {
+        Self {
+            type_: VAGenericValueType::Pointer,
+            value: VAGenericValueUnion { p },
+        }
+    }
+
     pub fn as_int(self) -> Option<i32> {
         if self.type_ == VAGenericValueType::Integer {
             unsafe { Some(self.value.i) }
@@ -327,6 +514,90 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Describes how a [`Surface`] is going to be used, allowing the driver to pick a suitable
+    /// memory layout.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SurfaceUsageHint: u32 {
+        /// The surface is used as the output of a decoder.
+        const DECODER   = 0x00000001;
+        /// The surface is used as the input of an encoder.
+        const ENCODER   = 0x00000002;
+        /// The surface is read by the video processor.
+        const VPP_READ  = 0x00000004;
+        /// The surface is written to by the video processor.
+        const VPP_WRITE = 0x00000008;
+        /// The surface is displayed.
+        const DISPLAY   = 0x00000010;
+        /// The surface is exported to another API.
+        const EXPORT    = 0x00000020;
+    }
+}
+
+/// A rectangular region of a [`Surface`] or [`Image`], in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    /// Creates a [`Rect`] with its top left corner at (`x`, `y`).
+    #[inline]
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[inline]
+    pub fn x(&self) -> u32 {
+        self.x
+    }
+
+    #[inline]
+    pub fn y(&self) -> u32 {
+        self.y
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns whether this [`Rect`] is non-empty and lies within a `width`x`height` area.
+    fn fits_in(&self, width: u32, height: u32) -> bool {
+        self.width != 0
+            && self.height != 0
+            && self
+                .x
+                .checked_add(self.width)
+                .is_some_and(|right| right <= width)
+            && self
+                .y
+                .checked_add(self.height)
+                .is_some_and(|bottom| bottom <= height)
+    }
+
+    /// Converts the position to the signed coordinates libva expects.
+    fn origin(&self) -> Result<(c_int, c_int)> {
+        Ok((
+            self.x.try_into().map_err(Error::from)?,
+            self.y.try_into().map_err(Error::from)?,
+        ))
+    }
+}
+
 /// A graphics surface or texture.
 ///
 /// A [`Surface`] acts as either the input of an encoding operation, or the output of a decoding
@@ -383,13 +654,23 @@ impl Surface {
         height: u32,
         format: RTFormat,
         attribs: &mut [SurfaceAttrib],
+    ) -> Result<Self> {
+        Self::create(&display.d, width, height, format, attribs)
+    }
+
+    pub(crate) fn create(
+        d: &Arc<DisplayOwner>,
+        width: u32,
+        height: u32,
+        format: RTFormat,
+        attribs: &mut [SurfaceAttrib],
     ) -> Result<Self> {
         let mut id = 0;
         unsafe {
             check(
                 "vaCreateSurfaces",
-                display.d.libva.vaCreateSurfaces(
-                    display.d.raw,
+                d.libva.vaCreateSurfaces(
+                    d.raw,
                     format,
                     width as c_uint,
                     height as c_uint,
@@ -400,10 +681,7 @@ impl Surface {
                 ),
             )?;
         }
-        Ok(Surface {
-            d: display.d.clone(),
-            id,
-        })
+        Ok(Surface { d: d.clone(), id })
     }
 
     #[inline]
@@ -426,6 +704,69 @@ impl Surface {
         Ok(())
     }
 
+    /// Waits until all pending operations on this [`Surface`] have completed, or `timeout` has
+    /// elapsed.
+    ///
+    /// Returns `true` if the operations have completed, and `false` if the timeout has elapsed
+    /// first. A zero `timeout` polls the [`Surface`] without blocking.
+    ///
+    /// If the driver does not support `vaSyncSurface2`, this falls back to polling
+    /// [`Surface::is_ready`] until `timeout` has elapsed. Like [`Surface::is_ready`], the fallback
+    /// does not report errors of the completed operations (for example, decoding errors).
+    pub fn sync_timeout(&mut self, timeout: Duration) -> Result<bool> {
+        const POLL_INTERVAL: Duration = Duration::from_micros(500);
+
+        let timeout_ns = u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX);
+        let status = unsafe { self.d.libva.vaSyncSurface2(self.d.raw, self.id, timeout_ns) };
+        if status == VAError::ERROR_TIMEDOUT {
+            return Ok(false);
+        }
+        if status == VAError::ERROR_UNIMPLEMENTED {
+            let deadline = Instant::now().checked_add(timeout);
+            loop {
+                if self.is_ready()? {
+                    return Ok(true);
+                }
+                let now = Instant::now();
+                let remaining = match deadline {
+                    Some(deadline) if deadline <= now => return Ok(false),
+                    Some(deadline) => deadline - now,
+                    None => POLL_INTERVAL,
+                };
+                thread::sleep(remaining.min(POLL_INTERVAL));
+            }
+        }
+        check("vaSyncSurface2", status)?;
+        Ok(true)
+    }
+
+    /// Returns the macroblock ranges that failed to decode in the last decode operation targeting
+    /// this [`Surface`].
+    ///
+    /// This should be called when [`Surface::sync`] fails with
+    /// [`VAError::ERROR_DECODING_ERROR`]. The erroneous regions can then be concealed, or a new
+    /// keyframe requested.
+    ///
+    /// The entries are copied out of memory owned by the driver, which is only valid until the
+    /// next decode operation. This has to be called before the next frame is decoded, or the
+    /// errors of the frame in question are lost.
+    pub fn decode_errors(&mut self) -> Result<vec::IntoIter<SurfaceDecodeMBErrors>> {
+        unsafe {
+            let mut info: *mut c_void = ptr::null_mut();
+            check(
+                "vaQuerySurfaceError",
+                self.d.libva.vaQuerySurfaceError(
+                    self.d.raw,
+                    self.id,
+                    VAError::ERROR_DECODING_ERROR.into(),
+                    &mut info,
+                ),
+            )?;
+
+            Ok(copy_decode_errors(info.cast()).into_iter())
+        }
+    }
+
     /// Returns the current [`SurfaceStatus`] of this [`Surface`].
     ///
     /// The [`SurfaceStatus`] indicates whether and how the [`Surface`] is currently being used by a
@@ -443,12 +784,47 @@ impl Surface {
         Ok(status)
     }
 
+    /// Returns whether all pending operations rendering to or from this [`Surface`] have
+    /// completed, without blocking.
+    ///
+    /// This is based on [`Surface::status`]. Unlike [`Surface::sync_timeout`], it does not report
+    /// errors of the completed operations.
+    pub fn is_ready(&self) -> Result<bool> {
+        Ok(self.status()?.0 & SurfaceStatus::Rendering.0 == 0)
+    }
+
     /// Copies all pixels from `self` to the given [`Image`].
     ///
     /// This calls `vaGetImage`, which may be expensive on some drivers (eg. Intel). If possible,
     /// [`SurfaceWithImage`] should be used, so that `vaDeriveImage` is used instead if the driver
     /// supports it.
     pub fn copy_to_image(&mut self, image: &mut Image) -> Result<()> {
+        let rect = Rect::new(0, 0, image.width().into(), image.height().into());
+        self.copy_region_to_image(rect, image)
+    }
+
+    /// Copies the pixels in `src` from `self` to the top left corner of the given [`Image`].
+    ///
+    /// This allows reading back a region of interest without transferring the whole [`Surface`].
+    /// Like [`Surface::copy_to_image`], this calls `vaGetImage`, which does not support scaling or
+    /// writing to a different position in the [`Image`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `src` is empty or larger than `image`, or if `vaGetImage` fails (for
+    /// example because `src` exceeds the bounds of the [`Surface`]).
+    pub fn copy_region_to_image(&mut self, src: Rect, image: &mut Image) -> Result<()> {
+        // The region is written to the top left corner of the image.
+        let dst = Rect::new(0, 0, src.width, src.height);
+        if !dst.fits_in(image.width().into(), image.height().into()) {
+            return Err(Error::from(format!(
+                "source region {src:?} does not fit into {}x{} image",
+                image.width(),
+                image.height()
+            )));
+        }
+        let (x, y) = src.origin()?;
+
         self.sync()?;
 
         let start = Instant::now();
@@ -459,10 +835,10 @@ impl Surface {
                 self.d.libva.vaGetImage(
                     self.d.raw,
                     self.id,
-                    0,
-                    0,
-                    image.width().into(),
-                    image.height().into(),
+                    x,
+                    y,
+                    src.width,
+                    src.height,
                     image.id(),
                 ),
             )?;
@@ -479,6 +855,33 @@ impl Surface {
     /// [`SurfaceWithImage`] should be used, so that `vaDeriveImage` is used instead if the driver
     /// supports it.
     pub fn copy_from_image(&mut self, image: &mut Image) -> Result<()> {
+        let rect = Rect::new(0, 0, image.width().into(), image.height().into());
+        self.put_image_scaled(image, rect, rect)
+    }
+
+    /// Copies the pixels in `src` from the given [`Image`] to the region `dst` of `self`.
+    ///
+    /// If `src` and `dst` differ in size, the pixels are scaled to fit. This calls `vaPutImage`;
+    /// not all drivers support scaling, in which case this returns an error when the sizes differ.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `src` or `dst` are empty, if `src` exceeds the bounds of `image`, or if
+    /// `vaPutImage` fails (for example because `dst` exceeds the bounds of the [`Surface`]).
+    pub fn put_image_scaled(&mut self, image: &mut Image, src: Rect, dst: Rect) -> Result<()> {
+        if !src.fits_in(image.width().into(), image.height().into()) {
+            return Err(Error::from(format!(
+                "source region {src:?} exceeds the bounds of the {}x{} image",
+                image.width(),
+                image.height()
+            )));
+        }
+        if dst.width == 0 || dst.height == 0 {
+            return Err(Error::from(format!("destination region {dst:?} is empty")));
+        }
+        let (src_x, src_y) = src.origin()?;
+        let (dst_x, dst_y) = dst.origin()?;
+
         self.sync()?;
 
         let start = Instant::now();
@@ -490,14 +893,14 @@ impl Surface {
                     self.d.raw,
                     self.id,
                     image.id(),
-                    0,
-                    0,
-                    image.width().into(),
-                    image.height().into(),
-                    0,
-                    0,
-                    image.width().into(),
-                    image.height().into(),
+                    src_x,
+                    src_y,
+                    src.width,
+                    src.height,
+                    dst_x,
+                    dst_y,
+                    dst.width,
+                    dst.height,
                 ),
             )?;
         }
@@ -566,7 +969,28 @@ impl SurfaceWithImage {
     /// The [`RTFormat`] of the [`Surface`] will be determined automatically based on the specified
     /// [`PixelFormat`].
     pub fn new(display: &Display, width: u32, height: u32, format: PixelFormat) -> Result<Self> {
-        let mut surface = Surface::with_pixel_format(display, width, height, format)?;
+        Self::create(&display.d, width, height, format)
+    }
+
+    pub(crate) fn create(
+        d: &Arc<DisplayOwner>,
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<Self> {
+        let rtformat = format.to_rtformat().ok_or_else(|| {
+            Error::from(format!(
+                "no RTFormat to go with the requested pixel format {:?}",
+                format
+            ))
+        })?;
+        let mut surface = Surface::create(
+            d,
+            width,
+            height,
+            rtformat,
+            &mut [SurfaceAttribEnum::PixelFormat(format).into()],
+        )?;
 
         // Try to use `vaDeriveImage` first, fall back if that fails.
         match surface.derive_image() {
@@ -589,7 +1013,7 @@ impl SurfaceWithImage {
                     format
                 );
 
-                let image = Image::new(display, ImageFormat::new(format), width, height)?;
+                let image = Image::create(d, ImageFormat::new(format), width, height)?;
                 Ok(Self {
                     surface,
                     image,
@@ -640,11 +1064,103 @@ impl DerefMut for SurfaceWithImage {
 }
 
 /// Enumeration of supported [`SurfaceAttrib`]utes.
-#[derive(Debug)]
+///
+/// The `DRMFormatModifiers` and `ExternalBufferDescriptor` variants hold copies of the data that
+/// the attribute's value points to. They are returned by [`SurfaceAttributes::enums`], but cannot
+/// be converted into a [`SurfaceAttrib`], since it cannot own that data. The safe wrappers
+/// [`Surface::import_prime`], [`Surface::from_user_memory`] and
+/// [`Surface::with_drm_format_modifiers`] create surfaces with these attributes instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum SurfaceAttribEnum {
     PixelFormat(PixelFormat),
+    MinWidth(u32),
+    MaxWidth(u32),
+    MinHeight(u32),
+    MaxHeight(u32),
     MemoryType(SurfaceAttribMemoryType),
+    UsageHint(SurfaceUsageHint),
+    /// Required alignment of the surface's width and height, in pixels.
+    ///
+    /// Both values are powers of two, at most 2<sup>15</sup>. Converting other values into a
+    /// [`SurfaceAttrib`] panics, since they cannot be represented.
+    AlignmentSize {
+        width: u32,
+        height: u32,
+    },
+    /// DRM format modifiers supported for the surface's memory layout.
+    ///
+    /// Converting this into a [`SurfaceAttrib`] panics.
+    DRMFormatModifiers(Vec<u64>),
+    /// Description of externally allocated surface memory.
+    ///
+    /// Converting this into a [`SurfaceAttrib`] panics.
+    ExternalBufferDescriptor(ExternalBufferDescriptor),
+}
+
+/// Description of externally allocated memory backing a [`Surface`].
+///
+/// Copied from the value of an `ExternalBufferDescriptor` attribute, see
+/// [`SurfaceAttribEnum::ExternalBufferDescriptor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalBufferDescriptor {
+    pixel_format: PixelFormat,
+    width: u32,
+    height: u32,
+    data_size: u32,
+    num_planes: u32,
+    pitches: [u32; 4],
+    offsets: [u32; 4],
+    buffers: Vec<usize>,
+    flags: u32,
+}
+
+impl ExternalBufferDescriptor {
+    #[inline]
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the total size of the memory, in bytes.
+    #[inline]
+    pub fn data_size(&self) -> u32 {
+        self.data_size
+    }
+
+    /// Returns the row pitch of each plane, in bytes.
+    #[inline]
+    pub fn pitches(&self) -> &[u32] {
+        &self.pitches[..self.num_planes as usize]
+    }
+
+    /// Returns the byte offset of each plane.
+    #[inline]
+    pub fn offsets(&self) -> &[u32] {
+        &self.offsets[..self.num_planes as usize]
+    }
+
+    /// Returns the handles of the memory buffers (addresses or file descriptors, depending on the
+    /// memory type).
+    #[inline]
+    pub fn buffers(&self) -> &[usize] {
+        &self.buffers
+    }
+
+    /// Returns the raw `VA_SURFACE_EXTBUF_DESC_*` flags.
+    #[inline]
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
 }
 
 impl From<SurfaceAttribEnum> for SurfaceAttrib {
@@ -654,10 +1170,48 @@ impl From<SurfaceAttribEnum> for SurfaceAttrib {
                 SurfaceAttribType::PixelFormat,
                 GenericValue::int(format.to_u32_le() as i32),
             ),
+            SurfaceAttribEnum::MinWidth(width) => {
+                (SurfaceAttribType::MinWidth, GenericValue::int(width as i32))
+            }
+            SurfaceAttribEnum::MaxWidth(width) => {
+                (SurfaceAttribType::MaxWidth, GenericValue::int(width as i32))
+            }
+            SurfaceAttribEnum::MinHeight(height) => (
+                SurfaceAttribType::MinHeight,
+                GenericValue::int(height as i32),
+            ),
+            SurfaceAttribEnum::MaxHeight(height) => (
+                SurfaceAttribType::MaxHeight,
+                GenericValue::int(height as i32),
+            ),
             SurfaceAttribEnum::MemoryType(ty) => (
                 SurfaceAttribType::MemoryType,
                 GenericValue::int(ty.bits() as i32),
             ),
+            SurfaceAttribEnum::UsageHint(hint) => (
+                SurfaceAttribType::UsageHint,
+                GenericValue::int(hint.bits() as i32),
+            ),
+            SurfaceAttribEnum::AlignmentSize { width, height } => {
+                let log2 = |alignment: u32| {
+                    assert!(
+                        alignment.is_power_of_two() && alignment <= 1 << 15,
+                        "surface alignment {alignment} is not a power of two up to 2^15"
+                    );
+                    alignment.trailing_zeros()
+                };
+                let value = log2(width) | log2(height) << 4;
+                (
+                    SurfaceAttribType::AlignmentSize,
+                    GenericValue::int(value as i32),
+                )
+            }
+            SurfaceAttribEnum::DRMFormatModifiers(_)
+            | SurfaceAttribEnum::ExternalBufferDescriptor(_) => {
+                panic!(
+                    "{value:?} cannot be converted into a `SurfaceAttrib`, which cannot own its data"
+                )
+            }
         };
 
         Self {
@@ -674,6 +1228,201 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn decode_errors_list() {
+        let entry = |status, start_mb, end_mb| SurfaceDecodeMBErrors {
+            status,
+            start_mb,
+            end_mb,
+            decode_error_type: DecodeErrorType::MBError,
+            num_mb: end_mb - start_mb + 1,
+            va_reserved: [0; VA_PADDING_LOW - 1],
+        };
+        // The entry after the terminator must not be read.
+        let list = [
+            entry(1, 0, 9),
+            entry(1, 20, 24),
+            entry(-1, 0, 0),
+            entry(1, 30, 39),
+        ];
+        let errors = unsafe { copy_decode_errors(list.as_ptr()) };
+        let ranges: Vec<_> = errors.iter().map(|e| (e.start_mb(), e.end_mb())).collect();
+        assert_eq!(ranges, [(0, 9), (20, 24)]);
+        assert_eq!(errors[1].num_mb(), 5);
+
+        assert!(unsafe { copy_decode_errors(&entry(-1, 0, 0)) }.is_empty());
+        assert!(unsafe { copy_decode_errors(ptr::null()) }.is_empty());
+    }
+
+    #[test]
+    fn attrib_enum_roundtrip() {
+        let attribs = [
+            SurfaceAttribEnum::PixelFormat(PixelFormat::NV12),
+            SurfaceAttribEnum::MinWidth(16),
+            SurfaceAttribEnum::MaxWidth(4096),
+            SurfaceAttribEnum::MinHeight(16),
+            SurfaceAttribEnum::MaxHeight(2304),
+            SurfaceAttribEnum::MemoryType(
+                SurfaceAttribMemoryType::VA | SurfaceAttribMemoryType::DRM_PRIME_2,
+            ),
+            SurfaceAttribEnum::UsageHint(SurfaceUsageHint::DECODER | SurfaceUsageHint::EXPORT),
+            SurfaceAttribEnum::AlignmentSize {
+                width: 64,
+                height: 32,
+            },
+        ];
+        for attrib in attribs {
+            assert_eq!(SurfaceAttrib::from(attrib.clone()).as_enum(), Some(attrib));
+        }
+
+        let attrib = SurfaceAttrib::from_pointer(
+            SurfaceAttribType::ExternalBufferDescriptor,
+            std::ptr::null_mut(),
+        );
+        assert_eq!(attrib.as_enum(), None);
+        assert_eq!(attrib.raw_value().as_pointer(), Some(std::ptr::null_mut()));
+
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SurfaceAttribEnum>();
+    }
+
+    #[test]
+    fn attrib_pointer_values() {
+        let mut modifiers = [0, 0x0100_0000_0000_0004];
+        let mut list = VADRMFormatModifierList {
+            num_modifiers: modifiers.len() as u32,
+            modifiers: modifiers.as_mut_ptr(),
+        };
+        let attrib = SurfaceAttrib::from_pointer(
+            SurfaceAttribType::DRMFormatModifiers,
+            ptr::addr_of_mut!(list).cast(),
+        );
+        assert_eq!(attrib.as_enum(), None);
+        assert_eq!(
+            unsafe { attrib.copy_value() },
+            Some(SurfaceAttribEnum::DRMFormatModifiers(modifiers.to_vec()))
+        );
+
+        let mut buffer = 0x1000_usize;
+        let mut desc = VASurfaceAttribExternalBuffers {
+            pixel_format: PixelFormat::NV12.to_u32_le(),
+            width: 64,
+            height: 32,
+            data_size: 3072,
+            num_planes: 2,
+            pitches: [64, 64, 0, 0],
+            offsets: [0, 2048, 0, 0],
+            buffers: &mut buffer,
+            num_buffers: 1,
+            flags: 0,
+            private_data: ptr::null_mut(),
+        };
+        let attrib = SurfaceAttrib::from_pointer(
+            SurfaceAttribType::ExternalBufferDescriptor,
+            ptr::addr_of_mut!(desc).cast(),
+        );
+        let Some(SurfaceAttribEnum::ExternalBufferDescriptor(copied)) =
+            (unsafe { attrib.copy_value() })
+        else {
+            panic!("external buffer descriptor was not decoded");
+        };
+        assert_eq!(copied.pixel_format(), PixelFormat::NV12);
+        assert_eq!((copied.width(), copied.height()), (64, 32));
+        assert_eq!(copied.pitches(), [64, 64]);
+        assert_eq!(copied.offsets(), [0, 2048]);
+        assert_eq!(copied.buffers(), [0x1000]);
+
+        // Null pointers are skipped, and other attributes are decoded as usual.
+        let attrib =
+            SurfaceAttrib::from_pointer(SurfaceAttribType::DRMFormatModifiers, ptr::null_mut());
+        assert_eq!(unsafe { attrib.copy_value() }, None);
+        let attrib = SurfaceAttrib::from(SurfaceAttribEnum::MinWidth(16));
+        assert_eq!(
+            unsafe { attrib.copy_value() },
+            Some(SurfaceAttribEnum::MinWidth(16))
+        );
+    }
+
+    #[test]
+    #[should_panic = "cannot be converted"]
+    fn attrib_pointer_value_into_attrib() {
+        let _ = SurfaceAttrib::from(SurfaceAttribEnum::DRMFormatModifiers(vec![0]));
+    }
+
+    #[test]
+    #[should_panic = "not a power of two"]
+    fn attrib_alignment_not_power_of_two() {
+        let _ = SurfaceAttrib::from(SurfaceAttribEnum::AlignmentSize {
+            width: 48,
+            height: 1,
+        });
+    }
+
+    #[test]
+    #[should_panic = "not a power of two"]
+    fn attrib_alignment_zero() {
+        let _ = SurfaceAttrib::from(SurfaceAttribEnum::AlignmentSize {
+            width: 1,
+            height: 0,
+        });
+    }
+
+    #[test]
+    fn sync_timeout() {
+        run_test(|display| {
+            let mut surface = test_surface(display);
+            assert!(surface.sync_timeout(Duration::from_secs(5)).unwrap());
+            assert!(surface.is_ready().unwrap());
+            assert!(surface.sync_timeout(Duration::ZERO).unwrap());
+        });
+    }
+
+    #[test]
+    fn region_copy() {
+        run_test(|display| {
+            let mut surface = test_surface(display);
+            let mut image = Image::new(display, ImageFormat::new(TEST_PIXELFORMAT), 2, 1).unwrap();
+
+            // Read back the green and blue pixels.
+            surface
+                .copy_region_to_image(Rect::new(1, 0, 2, 1), &mut image)
+                .unwrap();
+            surface.sync().unwrap();
+            assert_eq!(&image.map().unwrap()[..8], &TEST_DATA[4..12]);
+
+            // Write them back to the start of the surface, scaled to 4 pixels.
+            let src = Rect::new(0, 0, 2, 1);
+            if let Err(e) = surface.put_image_scaled(&mut image, src, Rect::new(0, 0, 4, 1)) {
+                log::warn!("skipping test; scaled vaPutImage is not supported: {e}");
+                return;
+            }
+
+            // The edges of the upscaled region match the source pixels exactly, while the inner
+            // pixels may be interpolated. The pixel after the region must be untouched.
+            let mut scaled = Image::new(display, ImageFormat::new(TEST_PIXELFORMAT), 5, 1).unwrap();
+            surface.copy_to_image(&mut scaled).unwrap();
+            surface.sync().unwrap();
+            let map = scaled.map().unwrap();
+            let (green, blue) = (&TEST_DATA[4..8], &TEST_DATA[8..12]);
+            assert_eq!(&map[0..4], green);
+            assert_eq!(&map[12..16], blue);
+            assert_eq!(&map[16..20], &TEST_DATA[16..20]);
+            for pixel in map[4..12].chunks(4) {
+                for (c, &value) in pixel.iter().enumerate() {
+                    let (a, b) = (green[c], blue[c]);
+                    assert!((a.min(b)..=a.max(b)).contains(&value), "{:?}", &map[..20]);
+                }
+            }
+
+            assert!(surface
+                .copy_region_to_image(Rect::new(0, 0, 3, 1), &mut image)
+                .is_err());
+            assert!(surface
+                .put_image_scaled(&mut image, Rect::new(1, 0, 2, 1), src)
+                .is_err());
+        });
+    }
+
     #[test]
     fn image_copy() {
         run_test(|display| {
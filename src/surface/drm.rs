@@ -2,26 +2,60 @@
 //!
 //! This wraps some of the functionality in `va_drmcommon.h`.
 //!
-//! Also see [`Surface::export_prime`].
+//! Also see [`Surface::export_prime`] and [`Surface::import_prime`].
 
 use core::fmt;
-use std::{mem::MaybeUninit, os::fd::RawFd};
+use std::{
+    array,
+    ffi::c_void,
+    io,
+    mem::{self, MaybeUninit},
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd},
+};
 
 use crate::{
     check,
+    display::Display,
     dlopen::{libva_wayland, wl_buffer},
-    PixelFormat, Result,
+    raw::VADRMFormatModifierList,
+    Error, PixelFormat, Result,
+};
+
+use super::{
+    ExportSurfaceFlags, Surface, SurfaceAttrib, SurfaceAttribEnum, SurfaceAttribMemoryType,
+    SurfaceAttribType,
 };
 
-use super::{ExportSurfaceFlags, Surface, SurfaceAttribMemoryType};
+/// DRM format modifier describing a linear (untiled) memory layout.
+pub const DRM_FORMAT_MOD_LINEAR: u64 = 0;
 
-// TODO: do we need to wrap this in Rust type that owns and releases the fds?
-// valgrind seems to indicate no (ie. they're closed automatically when some object is destroyed)
+/// DRM format modifier indicating that the memory layout is unknown or implicit.
+pub const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
 
 /// Describes how a [`Surface`] was exported to, or should be imported from, a set of DRM PRIME
 /// objects.
 ///
-/// Returned by [`Surface::export_prime`].
+/// The descriptor owns the DMA-BUF file descriptors of its [`PrimeObject`]s, and closes them when
+/// dropped.
+///
+/// Returned by [`Surface::export_prime`], and passed to [`Surface::import_prime`]. A descriptor for
+/// importing externally allocated buffers can be built with [`PrimeSurfaceDescriptor::new`],
+/// [`PrimeSurfaceDescriptor::with_object`] and [`PrimeSurfaceDescriptor::with_layer`]:
+///
+/// ```no_run
+/// # use std::os::fd::OwnedFd;
+/// # use fev::{PixelFormat, surface::drm::*};
+/// # let fd: OwnedFd = unimplemented!();
+/// # let (size, width, height, pitch) = (0, 0, 0, 0);
+/// // An NV12 image stored in a single DMA-BUF.
+/// let descriptor = PrimeSurfaceDescriptor::new(PixelFormat::NV12, width, height)
+///     .with_object(PrimeObject::new(fd, size, 0))
+///     .with_layer(
+///         PrimeLayer::new(PixelFormat::NV12)
+///             .with_plane(0, 0, pitch)
+///             .with_plane(0, pitch * height, pitch),
+///     );
+/// ```
 #[repr(C)]
 pub struct PrimeSurfaceDescriptor {
     fourcc: PixelFormat,
@@ -46,6 +80,99 @@ impl fmt::Debug for PrimeSurfaceDescriptor {
 }
 
 impl PrimeSurfaceDescriptor {
+    /// Creates an empty descriptor for a surface with the given FourCC code and size.
+    ///
+    /// Objects and layers have to be added with [`PrimeSurfaceDescriptor::with_object`] and
+    /// [`PrimeSurfaceDescriptor::with_layer`] before the descriptor can be imported.
+    pub fn new(fourcc: PixelFormat, width: u32, height: u32) -> Self {
+        Self {
+            fourcc,
+            width,
+            height,
+            num_objects: 0,
+            objects: array::from_fn(|_| PrimeObject::EMPTY),
+            num_layers: 0,
+            layers: array::from_fn(|_| PrimeLayer::new(fourcc)),
+        }
+    }
+
+    /// Creates a copy of this descriptor, duplicating all file descriptors.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if duplicating a file descriptor fails.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        let mut clone = Self::new(self.fourcc, self.width, self.height);
+        for object in self.objects() {
+            clone = clone.with_object(object.try_clone()?);
+        }
+        for layer in self.layers() {
+            clone = clone.with_layer(layer.clone());
+        }
+        Ok(clone)
+    }
+
+    /// Takes ownership of the file descriptors of all [`PrimeObject`]s, in object order.
+    ///
+    /// This is useful to pass the DMA-BUFs on to another API that takes ownership of them.
+    pub fn into_owned_fds(mut self) -> Vec<OwnedFd> {
+        let num_objects = self.num_objects as usize;
+        self.objects[..num_objects]
+            .iter_mut()
+            .map(|object| unsafe { OwnedFd::from_raw_fd(mem::replace(&mut object.fd, -1)) })
+            .collect()
+    }
+
+    /// Adds a [`PrimeObject`] to the descriptor.
+    ///
+    /// Objects are referenced by [`PrimeLayer::with_plane`] via their index, which is the number
+    /// of objects added before.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the descriptor already contains 4 objects.
+    pub fn with_object(mut self, object: PrimeObject) -> Self {
+        let index = self.num_objects as usize;
+        assert!(index < 4, "PRIME descriptor can hold at most 4 objects");
+        self.objects[index] = object;
+        self.num_objects += 1;
+        self
+    }
+
+    /// Adds a [`PrimeLayer`] to the descriptor.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the descriptor already contains 4 layers.
+    pub fn with_layer(mut self, layer: PrimeLayer) -> Self {
+        let index = self.num_layers as usize;
+        assert!(index < 4, "PRIME descriptor can hold at most 4 layers");
+        self.layers[index] = layer;
+        self.num_layers += 1;
+        self
+    }
+
+    /// Checks that the descriptor is complete and all planes reference existing objects.
+    fn validate(&self) -> Result<()> {
+        if self.num_objects == 0 || self.num_layers == 0 {
+            return Err(Error::from(
+                "PRIME descriptor must contain at least one object and one layer",
+            ));
+        }
+        for (i, layer) in self.layers().iter().enumerate() {
+            if layer.num_planes == 0 {
+                return Err(Error::from(format!("PRIME layer {i} has no planes")));
+            }
+            if let Some(plane) = layer.planes().find(|p| p.object_index >= self.num_objects) {
+                return Err(Error::from(format!(
+                    "PRIME layer {i} references object {}, but the descriptor only has {}",
+                    plane.object_index, self.num_objects
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Returns the FourCC code of the overall PRIME surface (eg. [`PixelFormat::NV12`]).
     #[inline]
     pub fn fourcc(&self) -> PixelFormat {
@@ -99,6 +226,8 @@ impl PrimeSurfaceDescriptor {
 }
 
 /// Describes a DRM PRIME object, represented as a DMA-BUF file descriptor.
+///
+/// The [`PrimeObject`] owns its file descriptor, and closes it when dropped.
 #[derive(Debug)]
 #[repr(C)]
 pub struct PrimeObject {
@@ -108,10 +237,50 @@ pub struct PrimeObject {
 }
 
 impl PrimeObject {
+    /// Placeholder for unused entries in a [`PrimeSurfaceDescriptor`], which must not close any
+    /// file descriptor.
+    const EMPTY: Self = Self {
+        fd: -1,
+        size: 0,
+        drm_format_modifier: 0,
+    };
+
+    /// Creates a [`PrimeObject`] describing a DMA-BUF.
+    ///
+    /// `size` is the size of the DMA-BUF in bytes, and `drm_format_modifier` describes the memory
+    /// layout of its contents (`DRM_FORMAT_MOD_LINEAR`, or 0, for linear images).
+    #[inline]
+    pub fn new(fd: OwnedFd, size: u32, drm_format_modifier: u64) -> Self {
+        Self {
+            fd: fd.into_raw_fd(),
+            size,
+            drm_format_modifier,
+        }
+    }
+
+    /// Creates a copy of this object, duplicating its file descriptor.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if duplicating the file descriptor fails.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            fd: self.fd().try_clone_to_owned()?.into_raw_fd(),
+            size: self.size,
+            drm_format_modifier: self.drm_format_modifier,
+        })
+    }
+
     /// Returns the DMA-BUF file descriptor representing this object.
     #[inline]
-    pub fn fd(&self) -> RawFd {
-        self.fd
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.fd) }
+    }
+
+    /// Takes ownership of the DMA-BUF file descriptor.
+    #[inline]
+    pub fn into_owned_fd(mut self) -> OwnedFd {
+        unsafe { OwnedFd::from_raw_fd(mem::replace(&mut self.fd, -1)) }
     }
 
     /// Returns the size of this object in bytes.
@@ -130,7 +299,30 @@ impl PrimeObject {
     }
 }
 
+impl Drop for PrimeObject {
+    fn drop(&mut self) {
+        if self.fd >= 0 {
+            drop(unsafe { OwnedFd::from_raw_fd(self.fd) });
+        }
+    }
+}
+
+impl AsFd for PrimeObject {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd()
+    }
+}
+
+impl AsRawFd for PrimeObject {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
 /// Describes how a surface layer maps to a PRIME object.
+#[derive(Clone)]
 #[repr(C)]
 pub struct PrimeLayer {
     drm_format: PixelFormat,
@@ -162,6 +354,38 @@ impl fmt::Debug for PrimeLayer {
 }
 
 impl PrimeLayer {
+    /// Creates a [`PrimeLayer`] without any planes.
+    ///
+    /// `drm_format` is the DRM FourCC code of the layer. For composed layers, this is the same as
+    /// the FourCC code of the surface.
+    pub fn new(drm_format: PixelFormat) -> Self {
+        Self {
+            drm_format,
+            num_planes: 0,
+            object_index: [0; 4],
+            offset: [0; 4],
+            pitch: [0; 4],
+        }
+    }
+
+    /// Adds a plane to this layer.
+    ///
+    /// `object_index` is the index of the [`PrimeObject`] storing the plane's data, `offset` is the
+    /// byte offset of the plane in that object, and `pitch` is the plane's row pitch in bytes.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the layer already contains 4 planes.
+    pub fn with_plane(mut self, object_index: u32, offset: u32, pitch: u32) -> Self {
+        let index = self.num_planes as usize;
+        assert!(index < 4, "PRIME layer can hold at most 4 planes");
+        self.object_index[index] = object_index;
+        self.offset[index] = offset;
+        self.pitch[index] = pitch;
+        self.num_planes += 1;
+        self
+    }
+
     #[inline]
     pub fn drm_format(&self) -> PixelFormat {
         self.drm_format
@@ -225,6 +449,9 @@ impl Surface {
     /// Uses [`SurfaceAttribMemoryType::DRM_PRIME_2`] internally, which must be supported by the
     /// driver in order for this method call to succeed.
     ///
+    /// The returned [`PrimeSurfaceDescriptor`] owns the exported file descriptors, and closes them
+    /// when dropped.
+    ///
     /// # Errors
     ///
     /// This may return an error of type `ERROR_UNSUPPORTED_MEMORY_TYPE` even though the PRIME
@@ -233,7 +460,8 @@ impl Surface {
     /// creating the [`Surface`], before submitting any VA-API operation.
     pub fn export_prime(&mut self, flags: ExportSurfaceFlags) -> Result<PrimeSurfaceDescriptor> {
         unsafe {
-            let mut descriptor: MaybeUninit<PrimeSurfaceDescriptor> = MaybeUninit::uninit();
+            // Zero-initialized, since the driver only fills in the used objects and layers.
+            let mut descriptor: MaybeUninit<PrimeSurfaceDescriptor> = MaybeUninit::zeroed();
             check(
                 "vaExportSurfaceHandle",
                 self.d.libva.vaExportSurfaceHandle(
@@ -244,15 +472,117 @@ impl Surface {
                     descriptor.as_mut_ptr().cast(),
                 ),
             )?;
-            Ok(descriptor.assume_init())
+            let mut descriptor = descriptor.assume_init();
+
+            // Mark the unused objects as empty, so that they don't close fd 0 when dropped.
+            let num_objects = descriptor.num_objects as usize;
+            for object in descriptor.objects.iter_mut().skip(num_objects) {
+                object.fd = -1;
+            }
+            Ok(descriptor)
         }
     }
 
+    /// Creates a [`Surface`] with the given [`PixelFormat`], whose memory layout uses one of the
+    /// given DRM format modifiers.
+    ///
+    /// This allows allocating surfaces that can be shared with other devices, for example a
+    /// linear-only surface for a display controller ([`DRM_FORMAT_MOD_LINEAR`]). Drivers usually
+    /// do not report which modifiers they support (see
+    /// [`SurfaceAttributes::drm_format_modifiers`]), so this generally has to be tried with the
+    /// modifiers the consumer accepts. The modifier that was chosen by the driver can be
+    /// determined with [`Surface::export_prime`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `modifiers` is empty, if `format` has no corresponding
+    /// [`RTFormat`][super::RTFormat], or if the driver supports none of the `modifiers`.
+    ///
+    /// [`SurfaceAttributes::drm_format_modifiers`]:
+    /// super::SurfaceAttributes::drm_format_modifiers
+    pub fn with_drm_format_modifiers(
+        display: &Display,
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        modifiers: &[u64],
+    ) -> Result<Surface> {
+        if modifiers.is_empty() {
+            return Err(Error::from(
+                "list of DRM format modifiers must not be empty",
+            ));
+        }
+        let rtformat = format.to_rtformat().ok_or_else(|| {
+            Error::from(format!(
+                "no RTFormat to go with the requested pixel format {:?}",
+                format
+            ))
+        })?;
+
+        // The driver only reads from the list.
+        let mut list = VADRMFormatModifierList {
+            num_modifiers: modifiers.len().try_into().map_err(Error::from)?,
+            modifiers: modifiers.as_ptr().cast_mut(),
+        };
+        let list_ptr = (&mut list as *mut VADRMFormatModifierList).cast();
+        Surface::create(
+            &display.d,
+            width,
+            height,
+            rtformat,
+            &mut [
+                SurfaceAttribEnum::PixelFormat(format).into(),
+                SurfaceAttrib::from_pointer(SurfaceAttribType::DRMFormatModifiers, list_ptr),
+            ],
+        )
+    }
+
+    /// Creates a [`Surface`] that uses the DMA-BUFs described by `descriptor` as its storage.
+    ///
+    /// This allows zero-copy processing of buffers allocated elsewhere, for example by a V4L2
+    /// camera or a Wayland compositor.
+    ///
+    /// Uses [`SurfaceAttribMemoryType::DRM_PRIME_2`] internally, which must be supported by the
+    /// driver in order for this method call to succeed.
+    ///
+    /// The file descriptors in `descriptor` are not consumed: the driver holds its own reference
+    /// to the DMA-BUFs, so the caller may close them after this call returns.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `descriptor` is incomplete or inconsistent, if its FourCC code has no
+    /// corresponding [`RTFormat`][super::RTFormat], or if the driver fails to import the
+    /// DMA-BUFs.
+    pub fn import_prime(display: &Display, descriptor: &PrimeSurfaceDescriptor) -> Result<Surface> {
+        descriptor.validate()?;
+        let format = descriptor.fourcc().to_rtformat().ok_or_else(|| {
+            Error::from(format!(
+                "no RTFormat to go with the PRIME descriptor's pixel format {:?}",
+                descriptor.fourcc()
+            ))
+        })?;
+
+        // The driver only reads from the descriptor.
+        let descriptor_ptr = descriptor as *const PrimeSurfaceDescriptor as *mut c_void;
+        Surface::create(
+            &display.d,
+            descriptor.width(),
+            descriptor.height(),
+            format,
+            &mut [
+                SurfaceAttribEnum::MemoryType(SurfaceAttribMemoryType::DRM_PRIME_2).into(),
+                SurfaceAttrib::from_pointer(
+                    SurfaceAttribType::ExternalBufferDescriptor,
+                    descriptor_ptr,
+                ),
+            ],
+        )
+    }
+
     /// Returns a pointer to the `wl_buffer` containing this [`Surface`]s pixel data.
     ///
-    /// This function will only succeed if the [`Display`][crate::display::Display] this [`Surface`]
-    /// was created from is using the Wayland backend. To check the VA-API backend type, use
-    /// [`Display::display_api`][crate::display::Display::display_api].
+    /// This function will only succeed if the [`Display`] this [`Surface`] was created from is
+    /// using the Wayland backend. To check the VA-API backend type, use [`Display::display_api`].
     ///
     /// The returned pointer is valid while the [`Surface`] exists.
     ///
@@ -280,3 +610,131 @@ impl Surface {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+
+    use crate::test::*;
+
+    use super::*;
+
+    #[test]
+    fn prime_roundtrip() {
+        run_test(|display| {
+            let mut surface = test_surface(display);
+            let exported = match surface
+                .export_prime(ExportSurfaceFlags::READ | ExportSurfaceFlags::COMPOSED_LAYERS)
+            {
+                Ok(descriptor) => descriptor,
+                Err(e) => {
+                    log::warn!("skipping test; PRIME export is not supported: {e}");
+                    return;
+                }
+            };
+
+            // Rebuild the descriptor from its parts to exercise the builder.
+            let mut descriptor =
+                PrimeSurfaceDescriptor::new(exported.fourcc(), exported.width(), exported.height());
+            for object in exported.objects() {
+                descriptor = descriptor.with_object(PrimeObject::new(
+                    object.fd().try_clone_to_owned().unwrap(),
+                    object.size(),
+                    object.drm_format_modifier(),
+                ));
+            }
+            for layer in exported.layers() {
+                let mut new_layer = PrimeLayer::new(layer.drm_format());
+                for plane in layer.planes() {
+                    new_layer =
+                        new_layer.with_plane(plane.object_index(), plane.offset(), plane.pitch());
+                }
+                descriptor = descriptor.with_layer(new_layer);
+            }
+
+            let mut imported = Surface::import_prime(display, &descriptor).unwrap();
+            imported.sync().unwrap();
+        });
+    }
+
+    /// Creates a descriptor that owns the write end of a pipe.
+    ///
+    /// The read end reaches EOF only once every copy of the write end has been closed, which
+    /// detects leaked file descriptors.
+    fn pipe_descriptor() -> (io::PipeReader, PrimeSurfaceDescriptor) {
+        let (reader, writer) = io::pipe().unwrap();
+        let descriptor = PrimeSurfaceDescriptor::new(PixelFormat::NV12, 64, 64)
+            .with_object(PrimeObject::new(writer.into(), 64 * 96, 0))
+            .with_layer(
+                PrimeLayer::new(PixelFormat::NV12)
+                    .with_plane(0, 0, 64)
+                    .with_plane(0, 64 * 64, 64),
+            );
+        (reader, descriptor)
+    }
+
+    #[test]
+    fn descriptor_owns_fds() {
+        let (mut reader, descriptor) = pipe_descriptor();
+        let clone = descriptor.try_clone().unwrap();
+        assert_ne!(
+            clone.objects()[0].as_raw_fd(),
+            descriptor.objects()[0].as_raw_fd()
+        );
+        assert_eq!(clone.layers()[0].num_planes(), 2);
+        drop(descriptor);
+        drop(clone);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert!(buf.is_empty());
+
+        let (mut reader, descriptor) = pipe_descriptor();
+        let fds = descriptor.into_owned_fds();
+        assert_eq!(fds.len(), 1);
+        let mut writer = io::PipeWriter::from(fds.into_iter().next().unwrap());
+        writer.write_all(b"ok").unwrap();
+        drop(writer);
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"ok");
+    }
+
+    #[test]
+    fn linear_modifier() {
+        run_test(|display| {
+            let mut surface = match Surface::with_drm_format_modifiers(
+                display,
+                64,
+                64,
+                PixelFormat::NV12,
+                &[DRM_FORMAT_MOD_LINEAR],
+            ) {
+                Ok(surface) => surface,
+                Err(e) => {
+                    log::warn!("skipping test; DRM format modifiers are not supported: {e}");
+                    return;
+                }
+            };
+            let descriptor = match surface.export_prime(ExportSurfaceFlags::READ) {
+                Ok(descriptor) => descriptor,
+                Err(e) => {
+                    log::warn!("skipping test; PRIME export is not supported: {e}");
+                    return;
+                }
+            };
+            for object in descriptor.objects() {
+                assert_eq!(object.drm_format_modifier(), DRM_FORMAT_MOD_LINEAR);
+            }
+        });
+    }
+
+    #[test]
+    fn invalid_descriptor() {
+        let descriptor = PrimeSurfaceDescriptor::new(PixelFormat::NV12, 64, 64)
+            .with_object(PrimeObject::new(io::pipe().unwrap().1.into(), 0, 0))
+            .with_layer(PrimeLayer::new(PixelFormat::NV12).with_plane(1, 0, 64));
+        assert!(descriptor.validate().is_err());
+        assert!(PrimeSurfaceDescriptor::new(PixelFormat::NV12, 64, 64)
+            .validate()
+            .is_err());
+    }
+}
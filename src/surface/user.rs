@@ -0,0 +1,401 @@
+use std::{marker::PhantomData, ops::Deref, ptr, slice, time::Duration};
+
+use crate::{
+    config::Config, display::Display, raw::VASurfaceAttribExternalBuffers, Entrypoint, Error,
+    PixelFormat, Profile, Result,
+};
+
+use super::{
+    Surface, SurfaceAttrib, SurfaceAttribEnum, SurfaceAttribMemoryType, SurfaceAttribType,
+};
+
+/// Describes the layout of an image stored in host memory.
+///
+/// Used by [`Surface::from_user_memory`]. Every plane of the [`PixelFormat`] has to be added with
+/// [`UserMemoryLayout::with_plane`], in plane order.
+#[derive(Debug, Clone)]
+pub struct UserMemoryLayout {
+    format: PixelFormat,
+    width: u32,
+    height: u32,
+    num_planes: u32,
+    pitches: [u32; 4],
+    offsets: [u32; 4],
+}
+
+impl UserMemoryLayout {
+    /// Creates a [`UserMemoryLayout`] without any planes, for an image with the given
+    /// [`PixelFormat`] and size.
+    pub fn new(format: PixelFormat, width: u32, height: u32) -> Self {
+        Self {
+            format,
+            width,
+            height,
+            num_planes: 0,
+            pitches: [0; 4],
+            offsets: [0; 4],
+        }
+    }
+
+    /// Adds a plane to the layout.
+    ///
+    /// `offset` is the byte offset of the plane in the memory, and `pitch` is the plane's row pitch
+    /// in bytes.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the layout already contains 4 planes.
+    pub fn with_plane(mut self, offset: u32, pitch: u32) -> Self {
+        let index = self.num_planes as usize;
+        assert!(index < 4, "user memory layout can hold at most 4 planes");
+        self.offsets[index] = offset;
+        self.pitches[index] = pitch;
+        self.num_planes += 1;
+        self
+    }
+
+    #[inline]
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the row pitch of each plane, in bytes.
+    #[inline]
+    pub fn pitches(&self) -> &[u32] {
+        &self.pitches[..self.num_planes as usize]
+    }
+
+    /// Returns the byte offset of each plane.
+    #[inline]
+    pub fn offsets(&self) -> &[u32] {
+        &self.offsets[..self.num_planes as usize]
+    }
+
+    /// Checks that the layout matches its [`PixelFormat`], and that every plane fits into `len`
+    /// bytes of memory.
+    ///
+    /// `alignment` is the width and height alignment in pixels required by the driver, if known.
+    fn validate(&self, len: usize, alignment: Option<(u32, u32)>) -> Result<()> {
+        let (width_alignment, height_alignment) = alignment.unwrap_or((1, 1));
+        // The driver may access the rows it adds when aligning the height, so those have to be
+        // backed by memory too.
+        let height = self
+            .height
+            .checked_next_multiple_of(height_alignment)
+            .ok_or_else(|| Error::from(format!("image height {} is too large", self.height)))?;
+        let planes = self.format.plane_sizes(self.width, height).ok_or_else(|| {
+            Error::from(format!(
+                "pixel format {:?} is not supported for user memory surfaces",
+                self.format
+            ))
+        })?;
+        if planes.len() != self.num_planes as usize {
+            return Err(Error::from(format!(
+                "{:?} images have {} planes, but the layout specifies {}",
+                self.format,
+                planes.len(),
+                self.num_planes
+            )));
+        }
+
+        // The width alignment is in pixels. The pitch alignment of each plane, in bytes, is the row
+        // size of an image that is exactly `width_alignment` pixels wide.
+        let pitch_alignments = self
+            .format
+            .plane_sizes(width_alignment, 1)
+            .into_iter()
+            .flatten()
+            .map(|plane| plane.width * plane.bytes_per_element);
+
+        let iter = planes.iter().zip(self.pitches()).zip(self.offsets());
+        for (i, (((plane, &pitch), &offset), pitch_alignment)) in
+            iter.zip(pitch_alignments).enumerate()
+        {
+            let row_size = u64::from(plane.width) * u64::from(plane.bytes_per_element);
+            if u64::from(pitch) < row_size {
+                return Err(Error::from(format!(
+                    "pitch of plane {i} ({pitch}) is smaller than its row size ({row_size})"
+                )));
+            }
+            if pitch % pitch_alignment != 0 {
+                return Err(Error::from(format!(
+                    "pitch of plane {i} ({pitch}) is not a multiple of the required alignment \
+                     ({pitch_alignment})"
+                )));
+            }
+            let end = u64::from(offset) + u64::from(pitch) * u64::from(plane.height);
+            if end > len as u64 {
+                return Err(Error::from(format!(
+                    "plane {i} ends at byte {end}, but the memory is only {len} bytes long"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Queries the surface alignment required by the driver, and whether it supports user memory
+/// surfaces at all.
+///
+/// Surface attributes can only be queried for a [`Config`], so this uses the video processing
+/// entrypoint. If that is unavailable, the requirements are unknown and `Ok(None)` is returned.
+fn query_alignment(display: &Display) -> Result<Option<(u32, u32)>> {
+    let attribs = match Config::new(display, Profile::None, Entrypoint::VideoProc)
+        .and_then(|config| config.query_surface_attributes())
+    {
+        Ok(attribs) => attribs,
+        Err(e) => {
+            log::debug!("could not query surface attributes, skipping alignment checks: {e}");
+            return Ok(None);
+        }
+    };
+
+    let mut alignment = None;
+    for attrib in &attribs.vec {
+        match attrib.as_enum() {
+            Some(SurfaceAttribEnum::MemoryType(ty))
+                if !ty.contains(SurfaceAttribMemoryType::USER_PTR) =>
+            {
+                return Err(Error::from(
+                    "the driver does not support surfaces backed by user memory",
+                ));
+            }
+            Some(SurfaceAttribEnum::AlignmentSize { width, height }) => {
+                alignment = Some((width, height));
+            }
+            _ => {}
+        }
+    }
+    Ok(alignment)
+}
+
+/// A [`Surface`] whose pixel data is stored in host memory borrowed from the caller.
+///
+/// Created by [`Surface::from_user_memory`]. This dereferences to the underlying [`Surface`], so it
+/// can be passed to encode and VPP operations reading from it. Mutable access to the [`Surface`] is
+/// not provided, since swapping it out would let it outlive the borrowed memory.
+///
+/// Dropping a [`UserSurface`] blocks until all pending operations on the surface have finished,
+/// since the driver may still be accessing the borrowed memory.
+#[derive(Debug)]
+pub struct UserSurface<'a> {
+    surface: Surface,
+    memory: *mut u8,
+    len: usize,
+    _p: PhantomData<&'a mut [u8]>,
+}
+
+// The raw pointer is only used like the `&'a mut [u8]` it was created from.
+unsafe impl Send for UserSurface<'_> {}
+unsafe impl Sync for UserSurface<'_> {}
+
+impl UserSurface<'_> {
+    /// Blocks until all pending operations on the surface have finished.
+    ///
+    /// See [`Surface::sync`].
+    pub fn sync(&mut self) -> Result<()> {
+        self.surface.sync()
+    }
+
+    /// Waits until all pending operations on the surface have finished, or `timeout` has elapsed.
+    ///
+    /// See [`Surface::sync_timeout`].
+    pub fn sync_timeout(&mut self, timeout: Duration) -> Result<bool> {
+        self.surface.sync_timeout(timeout)
+    }
+
+    /// Returns a [`Future`] that completes once all pending operations on the surface have
+    /// finished.
+    ///
+    /// See [`Surface::synced`].
+    ///
+    /// [`Future`]: std::future::Future
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    #[cfg(feature = "async")]
+    pub fn synced(&mut self) -> crate::future::SurfaceSynced<'_> {
+        self.surface.synced()
+    }
+
+    /// Waits for all pending operations on the surface, and then returns the memory backing it.
+    ///
+    /// This allows writing the next frame into the memory, reusing the [`UserSurface`].
+    pub fn memory_mut(&mut self) -> Result<&mut [u8]> {
+        self.surface.sync()?;
+        Ok(unsafe { slice::from_raw_parts_mut(self.memory, self.len) })
+    }
+}
+
+impl Drop for UserSurface<'_> {
+    fn drop(&mut self) {
+        // The borrow of the memory ends here, so the driver must be done with it.
+        if let Err(e) = self.surface.sync() {
+            log::error!("failed to sync user memory surface on drop: {e}");
+        }
+    }
+}
+
+impl Deref for UserSurface<'_> {
+    type Target = Surface;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.surface
+    }
+}
+
+impl Surface {
+    /// Creates a [`Surface`] that uses `memory` as its storage, without copying it.
+    ///
+    /// This allows CPU-produced frames to be encoded or processed without a
+    /// [`Surface::copy_from_image`] round-trip. `layout` describes where the planes of the image
+    /// are stored in `memory`. The memory stays borrowed until the returned [`UserSurface`] is
+    /// dropped; use [`UserSurface::memory_mut`] to write to it in the meantime.
+    ///
+    /// Uses [`SurfaceAttribMemoryType::USER_PTR`] internally, which must be supported by the
+    /// driver in order for this method call to succeed. Some drivers additionally require
+    /// `memory` to be page-aligned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `layout` does not match its [`PixelFormat`], if a plane does not fit
+    /// into `memory`, if a pitch does not meet the driver's [`SurfaceAttribEnum::AlignmentSize`]
+    /// requirement, or if the driver fails to create the surface.
+    pub fn from_user_memory<'a>(
+        display: &Display,
+        memory: &'a mut [u8],
+        layout: &UserMemoryLayout,
+    ) -> Result<UserSurface<'a>> {
+        let alignment = query_alignment(display)?;
+        layout.validate(memory.len(), alignment)?;
+        let rtformat = layout.format.to_rtformat().ok_or_else(|| {
+            Error::from(format!(
+                "no RTFormat to go with the requested pixel format {:?}",
+                layout.format
+            ))
+        })?;
+
+        // The driver only reads from the descriptor and the buffer list.
+        let mut buffer = memory.as_mut_ptr() as usize;
+        let mut descriptor = VASurfaceAttribExternalBuffers {
+            pixel_format: layout.format.to_u32_le(),
+            width: layout.width,
+            height: layout.height,
+            data_size: memory.len().try_into().map_err(Error::from)?,
+            num_planes: layout.num_planes,
+            pitches: layout.pitches,
+            offsets: layout.offsets,
+            buffers: &mut buffer,
+            num_buffers: 1,
+            flags: 0,
+            private_data: ptr::null_mut(),
+        };
+        let descriptor_ptr = (&mut descriptor as *mut VASurfaceAttribExternalBuffers).cast();
+        let surface = Surface::create(
+            &display.d,
+            layout.width,
+            layout.height,
+            rtformat,
+            &mut [
+                SurfaceAttribEnum::PixelFormat(layout.format).into(),
+                SurfaceAttribEnum::MemoryType(SurfaceAttribMemoryType::USER_PTR).into(),
+                SurfaceAttrib::from_pointer(
+                    SurfaceAttribType::ExternalBufferDescriptor,
+                    descriptor_ptr,
+                ),
+            ],
+        )?;
+
+        Ok(UserSurface {
+            surface,
+            memory: memory.as_mut_ptr(),
+            len: memory.len(),
+            _p: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        image::{Image, ImageFormat},
+        test::*,
+    };
+
+    use super::*;
+
+    fn nv12_layout(width: u32, height: u32, pitch: u32) -> UserMemoryLayout {
+        UserMemoryLayout::new(PixelFormat::NV12, width, height)
+            .with_plane(0, pitch)
+            .with_plane(pitch * height, pitch)
+    }
+
+    #[test]
+    fn layout_validation() {
+        let layout = nv12_layout(64, 64, 64);
+        layout.validate(64 * 96, None).unwrap();
+        // Too small for the chroma plane.
+        assert!(layout.validate(64 * 95, None).is_err());
+        // Pitch smaller than a row.
+        assert!(nv12_layout(64, 64, 32).validate(64 * 96, None).is_err());
+        // Misaligned pitch.
+        assert!(nv12_layout(48, 64, 48)
+            .validate(48 * 96, Some((64, 1)))
+            .is_err());
+        // The width alignment is in pixels, so the pitch of 4-byte pixels has to be a multiple of
+        // 4 times the alignment.
+        let rgba = |pitch| UserMemoryLayout::new(PixelFormat::RGBA, 16, 16).with_plane(0, pitch);
+        assert!(rgba(96).validate(96 * 16, Some((32, 1))).is_err());
+        rgba(128).validate(128 * 16, Some((32, 1))).unwrap();
+        // The height is padded to the alignment before checking the chroma plane.
+        assert!(layout.validate(64 * 96, Some((64, 128))).is_err());
+        // Missing chroma plane.
+        assert!(UserMemoryLayout::new(PixelFormat::NV12, 64, 64)
+            .with_plane(0, 64)
+            .validate(64 * 96, None)
+            .is_err());
+    }
+
+    #[test]
+    fn user_memory_surface() {
+        run_test(|display| {
+            let pitch = TEST_WIDTH * 4;
+            let layout = UserMemoryLayout::new(TEST_PIXELFORMAT, TEST_WIDTH, TEST_HEIGHT)
+                .with_plane(0, pitch);
+            // Page-aligned memory, as required by some drivers.
+            let mut storage = vec![0u8; (pitch * TEST_HEIGHT) as usize + 4096];
+            let start = storage.as_ptr().align_offset(4096);
+            let memory = &mut storage[start..][..(pitch * TEST_HEIGHT) as usize];
+            memory[..TEST_DATA.len()].copy_from_slice(TEST_DATA);
+
+            let mut surface = match Surface::from_user_memory(display, memory, &layout) {
+                Ok(surface) => surface,
+                Err(e) => {
+                    log::warn!("skipping test; user memory surfaces are not supported: {e}");
+                    return;
+                }
+            };
+            let mut image = Image::new(
+                display,
+                ImageFormat::new(TEST_PIXELFORMAT),
+                TEST_WIDTH,
+                TEST_HEIGHT,
+            )
+            .unwrap();
+            surface.surface.copy_to_image(&mut image).unwrap();
+            surface.sync().unwrap();
+            assert_eq!(&image.map().unwrap()[..TEST_DATA.len()], TEST_DATA);
+
+            surface.memory_mut().unwrap()[0] = 0;
+        });
+    }
+}
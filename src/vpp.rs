@@ -5,18 +5,33 @@
 //! [`ProcPipelineParameterBuffer`] as a
 //! [`BufferType::ProcPipelineParameter`][crate::buffer::BufferType::ProcPipelineParameter].
 
-use std::{ffi::c_uint, marker::PhantomData, mem, slice, vec};
+mod deinterlace;
+mod lut;
+mod tonemap;
+
+use std::{
+    ffi::{c_uint, c_void},
+    fmt,
+    marker::PhantomData,
+    mem, slice,
+    sync::Arc,
+    vec,
+};
 
 use crate::{
-    buffer::{Buffer, RawBuffer},
+    buffer::{Buffer, BufferType, RawBuffer},
     check,
     context::Context,
     pixelformat::PixelFormat,
     raw::{Rectangle, VABufferID, VASurfaceID, VA_PADDING_HIGH, VA_PADDING_LARGE, VA_PADDING_LOW},
     surface::Surface,
-    Mirror, Result, Rotation,
+    Error, Mirror, Result, Rotation,
 };
 
+pub use deinterlace::Deinterlacer;
+pub use lut::{Lut3D, Lut3DFilter};
+pub use tonemap::ToneMapper;
+
 impl Context {
     /// Fetches the list of supported video processing filter types.
     pub fn query_video_processing_filters(&self) -> Result<FilterTypes> {
@@ -54,6 +69,65 @@ impl Context {
         Ok(FilterTypes { filters })
     }
 
+    /// Queries the capabilities of a video processing filter.
+    ///
+    /// `T` must be the `VAProcFilterCap*` structure belonging to `filter`.
+    unsafe fn query_filter_caps<T: Copy>(&self, filter: FilterType, empty: T) -> Result<Vec<T>> {
+        // Like `vaQueryVideoProcFilters`, this does not reliably report the required length, so
+        // preallocate a "large" array.
+        const PREALLOC: usize = 64;
+
+        let mut num_caps = PREALLOC as c_uint;
+        let mut caps = vec![empty; PREALLOC];
+        check(
+            "vaQueryVideoProcFilterCaps",
+            self.d.libva.vaQueryVideoProcFilterCaps(
+                self.d.raw,
+                self.id,
+                filter,
+                caps.as_mut_ptr().cast(),
+                &mut num_caps,
+            ),
+        )?;
+
+        caps.truncate(num_caps as usize);
+        Ok(caps)
+    }
+
+    /// Returns the list of [`DeinterlacingType`]s supported by the deinterlacing filter.
+    pub fn query_deinterlacing_caps(&self) -> Result<Vec<DeinterlacingType>> {
+        let empty = FilterCapDeinterlacing {
+            type_: DeinterlacingType::None,
+            va_reserved: [0; VA_PADDING_LOW],
+        };
+        let caps = unsafe { self.query_filter_caps(FilterType::Deinterlacing, empty)? };
+        Ok(caps.into_iter().map(|cap| cap.type_).collect())
+    }
+
+    /// Returns the HDR metadata types and tone mapping modes supported by the HDR tone mapping
+    /// filter.
+    pub fn query_hdr_tone_mapping_caps(&self) -> Result<Vec<HdrToneMappingCap>> {
+        let empty = HdrToneMappingCap {
+            metadata_type: HighDynamicRangeMetadataType::None,
+            caps_flag: ToneMapping::empty(),
+            va_reserved: [0; VA_PADDING_HIGH],
+        };
+        unsafe { self.query_filter_caps(FilterType::HighDynamicRangeToneMapping, empty) }
+    }
+
+    /// Returns the list of table configurations supported by the 3D LUT filter.
+    pub fn query_lut3d_caps(&self) -> Result<Vec<Lut3DCap>> {
+        let empty = Lut3DCap {
+            lut_size: 0,
+            lut_stride: [0; 3],
+            bit_depth: 0,
+            num_channel: 0,
+            channel_mapping: Lut3DChannelMapping::empty(),
+            va_reserved: [0; VA_PADDING_HIGH],
+        };
+        unsafe { self.query_filter_caps(FilterType::LUT3D, empty) }
+    }
+
     pub fn query_video_processing_pipeline_caps(
         &self,
         filters: &mut Filters,
@@ -281,6 +355,33 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Flags controlling the behavior of the deinterlacing filter.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct DeinterlacingFlags: u32 {
+        /// The bottom field comes first in time.
+        const BOTTOM_FIELD_FIRST = 0x0001;
+        /// The bottom field is the one being processed (instead of the top field).
+        const BOTTOM_FIELD       = 0x0002;
+        /// Only a single field is present in the input surface.
+        const ONE_FIELD          = 0x0004;
+        /// Enable film mode detection.
+        const FMD_ENABLE         = 0x0008;
+        /// Enable scene change detection.
+        const SCD_ENABLE         = 0x0010;
+    }
+}
+
+bitflags! {
+    /// Describes which color channels a 3D LUT maps between.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Lut3DChannelMapping: u32 {
+        const RGB_RGB = 0x00000001;
+        const YUV_RGB = 0x00000002;
+        const VUY_RGB = 0x00000004;
+    }
+}
+
 bitflags! {
     /// The supported [`Rotation`]s.
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -346,6 +447,48 @@ impl ColorProperties {
         unsafe { mem::zeroed() }
     }
 
+    /// Returns the color primaries, as defined by ITU-T H.273.
+    #[inline]
+    pub fn colour_primaries(&self) -> u8 {
+        self.colour_primaries
+    }
+
+    /// Sets the color primaries, as defined by ITU-T H.273.
+    ///
+    /// Only used with [`ColorStandardType::Explicit`].
+    #[inline]
+    pub fn set_colour_primaries(&mut self, colour_primaries: u8) {
+        self.colour_primaries = colour_primaries;
+    }
+
+    /// Returns the transfer characteristics, as defined by ITU-T H.273.
+    #[inline]
+    pub fn transfer_characteristics(&self) -> u8 {
+        self.transfer_characteristics
+    }
+
+    /// Sets the transfer characteristics, as defined by ITU-T H.273.
+    ///
+    /// Only used with [`ColorStandardType::Explicit`].
+    #[inline]
+    pub fn set_transfer_characteristics(&mut self, transfer_characteristics: u8) {
+        self.transfer_characteristics = transfer_characteristics;
+    }
+
+    /// Returns the matrix coefficients, as defined by ITU-T H.273.
+    #[inline]
+    pub fn matrix_coefficients(&self) -> u8 {
+        self.matrix_coefficients
+    }
+
+    /// Sets the matrix coefficients, as defined by ITU-T H.273.
+    ///
+    /// Only used with [`ColorStandardType::Explicit`].
+    #[inline]
+    pub fn set_matrix_coefficients(&mut self, matrix_coefficients: u8) {
+        self.matrix_coefficients = matrix_coefficients;
+    }
+
     #[inline]
     pub fn chroma_sample_location(&self) -> ChromaSiting {
         self.chroma_sample_location
@@ -377,6 +520,155 @@ impl ColorProperties {
         self.color_range = color_range;
         self
     }
+
+    /// Sets the H.273 color primaries, transfer characteristics, and matrix coefficients.
+    #[inline]
+    pub fn with_explicit(
+        mut self,
+        colour_primaries: u8,
+        transfer_characteristics: u8,
+        matrix_coefficients: u8,
+    ) -> Self {
+        self.colour_primaries = colour_primaries;
+        self.transfer_characteristics = transfer_characteristics;
+        self.matrix_coefficients = matrix_coefficients;
+        self
+    }
+}
+
+/// HDR10 static metadata: mastering display color volume and content light level.
+///
+/// The values use the units of the corresponding HEVC SEI messages (and SMPTE ST 2086 / CTA-861.3).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct Hdr10Metadata {
+    display_primaries_x: [u16; 3],
+    display_primaries_y: [u16; 3],
+    white_point_x: u16,
+    white_point_y: u16,
+    max_display_mastering_luminance: u32,
+    min_display_mastering_luminance: u32,
+    max_content_light_level: u16,
+    max_pic_average_light_level: u16,
+    reserved: [u16; VA_PADDING_HIGH],
+}
+
+impl Hdr10Metadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the chromaticity coordinates of the mastering display's primaries, in increments
+    /// of 0.00002.
+    ///
+    /// The primaries are in G, B, R order.
+    #[inline]
+    pub fn display_primaries(&self) -> [(u16, u16); 3] {
+        [0, 1, 2].map(|i| (self.display_primaries_x[i], self.display_primaries_y[i]))
+    }
+
+    /// Sets the chromaticity coordinates of the mastering display's primaries, in increments of
+    /// 0.00002.
+    ///
+    /// The primaries are in G, B, R order.
+    #[inline]
+    pub fn set_display_primaries(&mut self, primaries: [(u16, u16); 3]) {
+        for (i, (x, y)) in primaries.into_iter().enumerate() {
+            self.display_primaries_x[i] = x;
+            self.display_primaries_y[i] = y;
+        }
+    }
+
+    /// Returns the chromaticity coordinates of the mastering display's white point, in increments
+    /// of 0.00002.
+    #[inline]
+    pub fn white_point(&self) -> (u16, u16) {
+        (self.white_point_x, self.white_point_y)
+    }
+
+    /// Sets the chromaticity coordinates of the mastering display's white point, in increments of
+    /// 0.00002.
+    #[inline]
+    pub fn set_white_point(&mut self, x: u16, y: u16) {
+        self.white_point_x = x;
+        self.white_point_y = y;
+    }
+
+    /// Returns the maximum luminance of the mastering display, in units of 0.0001 cd/m².
+    #[inline]
+    pub fn max_display_mastering_luminance(&self) -> u32 {
+        self.max_display_mastering_luminance
+    }
+
+    /// Sets the maximum luminance of the mastering display, in units of 0.0001 cd/m².
+    #[inline]
+    pub fn set_max_display_mastering_luminance(&mut self, luminance: u32) {
+        self.max_display_mastering_luminance = luminance;
+    }
+
+    /// Returns the minimum luminance of the mastering display, in units of 0.0001 cd/m².
+    #[inline]
+    pub fn min_display_mastering_luminance(&self) -> u32 {
+        self.min_display_mastering_luminance
+    }
+
+    /// Sets the minimum luminance of the mastering display, in units of 0.0001 cd/m².
+    #[inline]
+    pub fn set_min_display_mastering_luminance(&mut self, luminance: u32) {
+        self.min_display_mastering_luminance = luminance;
+    }
+
+    /// Returns the maximum content light level (MaxCLL) in cd/m².
+    #[inline]
+    pub fn max_content_light_level(&self) -> u16 {
+        self.max_content_light_level
+    }
+
+    /// Sets the maximum content light level (MaxCLL) in cd/m².
+    #[inline]
+    pub fn set_max_content_light_level(&mut self, level: u16) {
+        self.max_content_light_level = level;
+    }
+
+    /// Returns the maximum picture average light level (MaxFALL) in cd/m².
+    #[inline]
+    pub fn max_pic_average_light_level(&self) -> u16 {
+        self.max_pic_average_light_level
+    }
+
+    /// Sets the maximum picture average light level (MaxFALL) in cd/m².
+    #[inline]
+    pub fn set_max_pic_average_light_level(&mut self, level: u16) {
+        self.max_pic_average_light_level = level;
+    }
+}
+
+/// A reference to HDR metadata of a specific [`HighDynamicRangeMetadataType`].
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct HdrMetadata<'a> {
+    metadata_type: HighDynamicRangeMetadataType,
+    metadata: *const c_void,
+    metadata_size: u32,
+    reserved: [u32; VA_PADDING_LOW],
+    _p: PhantomData<&'a Hdr10Metadata>,
+}
+
+impl<'a> HdrMetadata<'a> {
+    pub fn hdr10(metadata: &'a Hdr10Metadata) -> Self {
+        Self {
+            metadata_type: HighDynamicRangeMetadataType::HDR10,
+            metadata: (metadata as *const Hdr10Metadata).cast(),
+            metadata_size: mem::size_of::<Hdr10Metadata>() as u32,
+            reserved: [0; VA_PADDING_LOW],
+            _p: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn metadata_type(&self) -> HighDynamicRangeMetadataType {
+        self.metadata_type
+    }
 }
 
 /// Collection of supported [`FilterType`]s.
@@ -392,6 +684,10 @@ impl FilterTypes {
     pub fn is_empty(&self) -> bool {
         self.filters.is_empty()
     }
+
+    pub fn contains(&self, filter: FilterType) -> bool {
+        self.filters.contains(&filter)
+    }
 }
 
 impl IntoIterator for FilterTypes {
@@ -431,7 +727,7 @@ pub struct ProcPipelineParameterBuffer<'a> {
     input_color_properties: ColorProperties,
     output_color_properties: ColorProperties,
     processing_mode: ProcMode,
-    output_hdr_metadata: *const u64, // TODO port struct
+    output_hdr_metadata: *const HdrMetadata<'a>, // may be NULL
 
     va_reserved: [u32; if cfg!(target_pointer_width = "64") {
         VA_PADDING_LARGE - 16
@@ -453,6 +749,27 @@ impl<'a> ProcPipelineParameterBuffer<'a> {
         this
     }
 
+    /// Restricts processing to a region of the input [`Surface`].
+    #[inline]
+    pub(crate) fn set_surface_region(&mut self, region: &'a Rectangle) {
+        self.surface_region = region;
+    }
+
+    /// Returns the processed region of the input [`Surface`], or [`None`] if the whole surface
+    /// is processed.
+    #[inline]
+    fn surface_region(&self) -> Option<Rectangle> {
+        // The pointer is either null, or borrowed for `'a`.
+        unsafe { self.surface_region.as_ref().copied() }
+    }
+
+    /// Returns the region of the output [`Surface`] that is written to, or [`None`] if the whole
+    /// surface is written to.
+    #[inline]
+    fn output_region(&self) -> Option<Rectangle> {
+        unsafe { self.output_region.as_ref().copied() }
+    }
+
     #[inline]
     pub fn set_filters(&mut self, filters: &'a mut Filters) {
         self.filters = filters.as_mut_ptr();
@@ -499,21 +816,213 @@ impl<'a> ProcPipelineParameterBuffer<'a> {
         self.output_color_properties = props;
     }
 
+    #[inline]
+    pub fn filter_flags(&self) -> FilterFlags {
+        self.filter_flags
+    }
+
     #[inline]
     pub fn set_filter_flags(&mut self, flags: FilterFlags) {
         self.filter_flags = flags;
     }
 
+    /// Sets the list of forward reference frames (past frames) used by filters like motion-adaptive
+    /// deinterlacing.
+    ///
+    /// The number of references required is given by
+    /// [`ProcPipelineCaps::num_forward_references`].
+    #[inline]
+    pub fn set_forward_references(&mut self, refs: &'a References<'a>) {
+        self.forward_references = refs.as_ptr();
+        self.num_forward_references = refs.len().try_into().unwrap();
+    }
+
+    /// Sets the list of backward reference frames (future frames) used by filters like
+    /// motion-adaptive deinterlacing.
+    ///
+    /// The number of references required is given by
+    /// [`ProcPipelineCaps::num_backward_references`].
+    #[inline]
+    pub fn set_backward_references(&mut self, refs: &'a References<'a>) {
+        self.backward_references = refs.as_ptr();
+        self.num_backward_references = refs.len().try_into().unwrap();
+    }
+
+    /// Sets the list of additional output [`Surface`]s to write the processed image to.
+    ///
+    /// Every additional output receives the same processed image as the main output (the
+    /// [`Surface`] passed to [`Context::begin_picture`]), scaled to the size of the additional
+    /// [`Surface`]. This allows producing several renditions of a frame in a single submission.
+    #[inline]
+    pub fn set_additional_outputs(&mut self, outputs: &'a AdditionalOutputs<'a>) {
+        self.additional_outputs = outputs.ids.as_ptr().cast_mut();
+        self.num_additional_outputs = outputs.ids.len().try_into().unwrap();
+    }
+
+    #[inline]
+    pub fn pipeline_flags(&self) -> PipelineFlags {
+        self.pipeline_flags
+    }
+
+    #[inline]
+    pub fn set_pipeline_flags(&mut self, flags: PipelineFlags) {
+        self.pipeline_flags = flags;
+    }
+
+    #[inline]
+    pub fn rotation(&self) -> Rotation {
+        self.rotation_state
+    }
+
     #[inline]
     pub fn set_rotation(&mut self, rot: Rotation) {
         self.rotation_state = rot;
     }
+
+    #[inline]
+    pub fn mirror(&self) -> Mirror {
+        self.mirror_state
+    }
+
+    #[inline]
+    pub fn set_mirror(&mut self, mirror: Mirror) {
+        self.mirror_state = mirror;
+    }
+
+    #[inline]
+    pub fn blend_state(&self) -> Option<&'a BlendState> {
+        unsafe { self.blend_state.as_ref() }
+    }
+
+    #[inline]
+    pub fn set_blend_state(&mut self, state: &'a BlendState) {
+        self.blend_state = state;
+    }
+
+    /// Sets the HDR metadata describing the output [`Surface`].
+    ///
+    /// This is needed when tone mapping to an HDR output. When tone mapping to SDR, no output
+    /// metadata is needed.
+    #[inline]
+    pub fn set_output_hdr_metadata(&mut self, metadata: &'a HdrMetadata<'a>) {
+        self.output_hdr_metadata = metadata;
+    }
+}
+
+/// A list of reference [`Surface`]s passed to a video processing pipeline.
+#[derive(Default)]
+pub struct References<'a> {
+    ids: Vec<VASurfaceID>,
+    _p: PhantomData<&'a Surface>,
 }
 
+impl<'a> References<'a> {
+    pub fn new() -> Self {
+        Self {
+            ids: Vec::new(),
+            _p: PhantomData,
+        }
+    }
+
+    pub fn push(&mut self, surface: &'a Surface) {
+        self.ids.push(surface.id());
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    fn as_ptr(&self) -> *mut VASurfaceID {
+        // libva never writes through this pointer, it's just missing a `const`.
+        self.ids.as_ptr().cast_mut()
+    }
+}
+
+/// A list of additional output [`Surface`]s of a video processing pipeline.
+///
+/// See [`ProcPipelineParameterBuffer::set_additional_outputs`].
+pub struct AdditionalOutputs<'a> {
+    ids: Vec<VASurfaceID>,
+    max: usize,
+    min_size: (u32, u32),
+    max_size: (u32, u32),
+    _p: PhantomData<&'a mut Surface>,
+}
+
+impl<'a> AdditionalOutputs<'a> {
+    /// Creates an empty list of additional outputs, limited to the number of additional outputs
+    /// and the output sizes supported by a pipeline with the given [`ProcPipelineCaps`].
+    pub fn new(caps: &ProcPipelineCaps) -> Self {
+        Self {
+            ids: Vec::new(),
+            max: caps.num_additional_outputs() as usize,
+            min_size: (caps.min_output_width(), caps.min_output_height()),
+            max_size: (caps.max_output_width(), caps.max_output_height()),
+            _p: PhantomData,
+        }
+    }
+
+    /// Adds an output [`Surface`] of size `width` x `height` to the list.
+    ///
+    /// The [`Surface`] is borrowed mutably until the list is dropped, since the video processing
+    /// operation will write to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pipeline does not support any more additional outputs, or if the
+    /// size of the [`Surface`] is outside of the output size range supported by the pipeline.
+    pub fn push(&mut self, surface: &'a mut Surface, width: u32, height: u32) -> Result<()> {
+        if self.ids.len() >= self.max {
+            return Err(Error::from(format!(
+                "video processing pipeline only supports {} additional output(s)",
+                self.max
+            )));
+        }
+        if !size_in_range(width, height, self.min_size, self.max_size) {
+            return Err(Error::from(format!(
+                "additional output size {width}x{height} is outside of the supported range"
+            )));
+        }
+        self.ids.push(surface.id());
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+/// Trait for filter parameter structures that can be added to [`Filters`].
+///
+/// # Safety
+///
+/// The implementing type must be a `#[repr(C)]` structure that begins with a [`FilterType`] field
+/// and matches the layout of the `VAProcFilterParameterBuffer*` structure libva expects for that
+/// filter type.
+pub unsafe trait FilterParameters: Copy + 'static {}
+
+unsafe impl FilterParameters for FilterParameterBuffer {}
+unsafe impl FilterParameters for DeinterlacingParameterBuffer {}
+unsafe impl FilterParameters for HdrToneMappingParameterBuffer {}
+unsafe impl FilterParameters for Lut3DParameterBuffer {}
+
 /// A collection of video processing filters, applied in sequence.
 pub struct Filters {
     buffers: Vec<RawBuffer>,
     ids: Vec<VABufferID>,
+    /// Metadata referenced by HDR tone mapping filter buffers. Boxed so that its address is stable.
+    #[allow(clippy::vec_box)]
+    hdr_metadata: Vec<Box<Hdr10Metadata>>,
+    /// Surfaces storing the tables used by 3D LUT filters.
+    lut_surfaces: Vec<Arc<Surface>>,
 }
 
 impl Filters {
@@ -521,17 +1030,47 @@ impl Filters {
         Self {
             buffers: Vec::new(),
             ids: Vec::new(),
+            hdr_metadata: Vec::new(),
+            lut_surfaces: Vec::new(),
         }
     }
 
-    #[allow(unused)]
-    fn push<T: 'static>(&mut self, buffer: Buffer<T>) {
-        // FIXME: once we have types for filter parameters, this should use a trait bound restricting them
+    /// Appends a filter to the end of the filter chain.
+    ///
+    /// `buffer` should be created with
+    /// [`BufferType::ProcFilterParameter`][crate::buffer::BufferType::ProcFilterParameter].
+    pub fn push<T: FilterParameters>(&mut self, buffer: Buffer<T>) {
         let id = buffer.id();
         self.buffers.push(buffer.into());
         self.ids.push(id);
     }
 
+    /// Appends an HDR tone mapping filter to the end of the filter chain.
+    ///
+    /// `metadata` describes the HDR10 input [`Surface`]. The tone mapping direction is determined
+    /// by the input and output color properties of the [`ProcPipelineParameterBuffer`].
+    pub fn push_hdr_tone_mapping(&mut self, cx: &Context, metadata: Hdr10Metadata) -> Result<()> {
+        let metadata = Box::new(metadata);
+        let params = HdrToneMappingParameterBuffer {
+            type_: FilterType::HighDynamicRangeToneMapping,
+            data: HdrMetadata {
+                metadata_type: HighDynamicRangeMetadataType::HDR10,
+                metadata: (&*metadata as *const Hdr10Metadata).cast(),
+                metadata_size: mem::size_of::<Hdr10Metadata>() as u32,
+                reserved: [0; VA_PADDING_LOW],
+                _p: PhantomData,
+            },
+            va_reserved: [0; VA_PADDING_HIGH],
+        };
+        self.push(Buffer::new_param(
+            cx,
+            BufferType::ProcFilterParameter,
+            params,
+        )?);
+        self.hdr_metadata.push(metadata);
+        Ok(())
+    }
+
     pub fn len(&self) -> usize {
         self.ids.len()
     }
@@ -552,15 +1091,47 @@ pub struct FilterValueRange {
     va_reserved: [u32; VA_PADDING_LOW],
 }
 
+/// Describes how the input is blended onto the output [`Surface`].
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct BlendState {
-    flags: c_uint,
+    flags: BlendFlags,
     global_alpha: f32,
     min_luma: f32,
     max_luma: f32,
 }
 
+impl BlendState {
+    pub fn new(flags: BlendFlags) -> Self {
+        Self {
+            flags,
+            global_alpha: 1.0,
+            min_luma: 0.0,
+            max_luma: 1.0,
+        }
+    }
+
+    #[inline]
+    pub fn flags(&self) -> BlendFlags {
+        self.flags
+    }
+
+    /// Sets the global alpha value (0.0-1.0) used with [`BlendFlags::GLOBAL_ALPHA`].
+    #[inline]
+    pub fn with_global_alpha(mut self, global_alpha: f32) -> Self {
+        self.global_alpha = global_alpha;
+        self
+    }
+
+    /// Sets the luma range (0.0-1.0) that is keyed out with [`BlendFlags::LUMA_KEY`].
+    #[inline]
+    pub fn with_luma_key(mut self, min_luma: f32, max_luma: f32) -> Self {
+        self.min_luma = min_luma;
+        self.max_luma = max_luma;
+        self
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Clone, Copy)]
 #[repr(C)]
@@ -577,6 +1148,291 @@ pub struct FilterParameterBuffer {
     va_reserved: [u32; VA_PADDING_LOW],
 }
 
+/// Parameters of the [`FilterType::Deinterlacing`] filter.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct DeinterlacingParameterBuffer {
+    type_: FilterType,
+    algorithm: DeinterlacingType,
+    flags: DeinterlacingFlags,
+    va_reserved: [u32; VA_PADDING_LOW],
+}
+
+impl DeinterlacingParameterBuffer {
+    pub fn new(algorithm: DeinterlacingType, flags: DeinterlacingFlags) -> Self {
+        Self {
+            type_: FilterType::Deinterlacing,
+            algorithm,
+            flags,
+            va_reserved: [0; VA_PADDING_LOW],
+        }
+    }
+
+    #[inline]
+    pub fn algorithm(&self) -> DeinterlacingType {
+        self.algorithm
+    }
+
+    #[inline]
+    pub fn flags(&self) -> DeinterlacingFlags {
+        self.flags
+    }
+}
+
+/// Parameters of the [`FilterType::HighDynamicRangeToneMapping`] filter.
+///
+/// Created by [`Filters::push_hdr_tone_mapping`], which keeps the referenced metadata alive.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct HdrToneMappingParameterBuffer {
+    type_: FilterType,
+    data: HdrMetadata<'static>,
+    va_reserved: [u32; VA_PADDING_HIGH],
+}
+
+/// A supported combination of HDR metadata type and [`ToneMapping`] modes.
+///
+/// Returned by [`Context::query_hdr_tone_mapping_caps`].
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct HdrToneMappingCap {
+    metadata_type: HighDynamicRangeMetadataType,
+    caps_flag: ToneMapping,
+    va_reserved: [u16; VA_PADDING_HIGH],
+}
+
+impl HdrToneMappingCap {
+    #[inline]
+    pub fn metadata_type(&self) -> HighDynamicRangeMetadataType {
+        self.metadata_type
+    }
+
+    #[inline]
+    pub fn tone_mapping(&self) -> ToneMapping {
+        self.caps_flag
+    }
+}
+
+impl fmt::Debug for HdrToneMappingCap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HdrToneMappingCap")
+            .field("metadata_type", &self.metadata_type)
+            .field("tone_mapping", &self.caps_flag)
+            .finish()
+    }
+}
+
+/// Parameters of the [`FilterType::LUT3D`] filter.
+///
+/// Created by [`Lut3DFilter::new`].
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Lut3DParameterBuffer {
+    type_: FilterType,
+    lut_surface: VASurfaceID,
+    lut_size: u16,
+    lut_stride: [u16; 3],
+    bit_depth: u16,
+    num_channel: u16,
+    channel_mapping: Lut3DChannelMapping,
+    va_reserved: [u32; VA_PADDING_HIGH],
+}
+
+impl Lut3DParameterBuffer {
+    fn new(lut_surface: &Surface, cap: &Lut3DCap) -> Self {
+        Self {
+            type_: FilterType::LUT3D,
+            lut_surface: lut_surface.id(),
+            lut_size: cap.lut_size,
+            lut_stride: cap.lut_stride,
+            bit_depth: cap.bit_depth,
+            num_channel: cap.num_channel,
+            channel_mapping: Lut3DChannelMapping::RGB_RGB,
+            va_reserved: [0; VA_PADDING_HIGH],
+        }
+    }
+}
+
+/// A table configuration supported by the 3D LUT filter.
+///
+/// Returned by [`Context::query_lut3d_caps`].
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Lut3DCap {
+    lut_size: u16,
+    lut_stride: [u16; 3],
+    bit_depth: u16,
+    num_channel: u16,
+    channel_mapping: Lut3DChannelMapping,
+    va_reserved: [u32; VA_PADDING_HIGH],
+}
+
+impl Lut3DCap {
+    /// Returns the number of valid points along each axis of the table.
+    #[inline]
+    pub fn lut_size(&self) -> u16 {
+        self.lut_size
+    }
+
+    /// Returns the number of points allocated along each axis of the table (including padding).
+    #[inline]
+    pub fn lut_stride(&self) -> [u16; 3] {
+        self.lut_stride
+    }
+
+    /// Returns the number of bits of each channel of a table entry.
+    #[inline]
+    pub fn bit_depth(&self) -> u16 {
+        self.bit_depth
+    }
+
+    /// Returns the number of channels of each table entry.
+    #[inline]
+    pub fn num_channel(&self) -> u16 {
+        self.num_channel
+    }
+
+    #[inline]
+    pub fn channel_mapping(&self) -> Lut3DChannelMapping {
+        self.channel_mapping
+    }
+}
+
+impl fmt::Debug for Lut3DCap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lut3DCap")
+            .field("lut_size", &self.lut_size)
+            .field("lut_stride", &self.lut_stride)
+            .field("bit_depth", &self.bit_depth)
+            .field("num_channel", &self.num_channel)
+            .field("channel_mapping", &self.channel_mapping)
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct FilterCapDeinterlacing {
+    type_: DeinterlacingType,
+    va_reserved: [u32; VA_PADDING_LOW],
+}
+
+/// An operation requested in a [`ProcPipelineParameterBuffer`] that is not supported by the driver.
+///
+/// Returned by [`ProcPipelineCaps::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnsupportedOperation {
+    /// The contained [`PipelineFlags`] are not supported.
+    PipelineFlags(PipelineFlags),
+    /// The requested [`Rotation`] is not supported.
+    Rotation(Rotation),
+    /// The contained [`Mirror`]ing directions are not supported.
+    Mirror(Mirror),
+    /// The contained [`BlendFlags`] are not supported.
+    Blend(BlendFlags),
+    /// The input color standard is not supported.
+    InputColorStandard(ColorStandardType),
+    /// The output color standard is not supported.
+    OutputColorStandard(ColorStandardType),
+    /// More [`AdditionalOutputs`] were requested than are supported.
+    AdditionalOutputs { requested: u32, supported: u32 },
+    /// The size of the input [`Surface`] is outside of the supported range.
+    InputSize { width: u32, height: u32 },
+    /// The size of the output [`Surface`] is outside of the supported range.
+    OutputSize { width: u32, height: u32 },
+    /// The processed region of the input [`Surface`] does not fit into the surface, or its size is
+    /// outside of the supported range.
+    InputRegion {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    },
+    /// The written region of the output [`Surface`] does not fit into the surface, or its size is
+    /// outside of the supported range.
+    OutputRegion {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    },
+    /// The [`PixelFormat`] of the input [`Surface`] is not supported.
+    InputPixelFormat(PixelFormat),
+    /// The [`PixelFormat`] of the output [`Surface`] is not supported.
+    OutputPixelFormat(PixelFormat),
+}
+
+/// Size and [`PixelFormat`] of a [`Surface`] taking part in a video processing operation.
+///
+/// [`Surface`]s do not keep track of their size and format, so this has to be passed to
+/// [`ProcPipelineCaps::validate`] separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcSurface {
+    width: u32,
+    height: u32,
+    pixel_format: Option<PixelFormat>,
+}
+
+impl ProcSurface {
+    /// Describes a [`Surface`] of the given size, with an unknown [`PixelFormat`].
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixel_format: None,
+        }
+    }
+
+    /// Sets the [`PixelFormat`] of the [`Surface`].
+    pub fn with_pixel_format(mut self, pixel_format: PixelFormat) -> Self {
+        self.pixel_format = Some(pixel_format);
+        self
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[inline]
+    pub fn pixel_format(&self) -> Option<PixelFormat> {
+        self.pixel_format
+    }
+}
+
+/// Returns whether `width` and `height` are within the given bounds, where a maximum of 0 means
+/// that the maximum is unknown.
+fn size_in_range(width: u32, height: u32, min: (u32, u32), max: (u32, u32)) -> bool {
+    width >= min.0
+        && height >= min.1
+        && (max.0 == 0 || width <= max.0)
+        && (max.1 == 0 || height <= max.1)
+}
+
+/// Returns the position and size of `region` if it does not fit into `surface`, or if its size is
+/// not within the given bounds.
+fn unsupported_region(
+    region: Rectangle,
+    surface: ProcSurface,
+    min: (u32, u32),
+    max: (u32, u32),
+) -> Option<(i32, i32, u32, u32)> {
+    let (x, y) = (i32::from(region.x()), i32::from(region.y()));
+    let (width, height) = (u32::from(region.width()), u32::from(region.height()));
+    let supported = x >= 0
+        && y >= 0
+        && x as u32 + width <= surface.width
+        && y as u32 + height <= surface.height
+        && size_in_range(width, height, min, max);
+    (!supported).then_some((x, y, width, height))
+}
+
 /// Capabilities of a video processing pipeline.
 ///
 /// Returned by [`Context::query_video_processing_pipeline_caps`].
@@ -609,6 +1465,213 @@ impl ProcPipelineCaps {
         self.raw.num_backward_references
     }
 
+    /// Returns the maximum number of [`AdditionalOutputs`] supported by the pipeline.
+    #[inline]
+    pub fn num_additional_outputs(&self) -> u32 {
+        self.raw.num_additional_outputs
+    }
+
+    /// Returns the supported [`Rotation`]s.
+    #[inline]
+    pub fn rotation_flags(&self) -> RotationFlags {
+        self.raw.rotation_flags
+    }
+
+    /// Returns the supported [`BlendFlags`].
+    #[inline]
+    pub fn blend_flags(&self) -> BlendFlags {
+        self.raw.blend_flags
+    }
+
+    /// Returns the supported [`Mirror`]ing directions.
+    #[inline]
+    pub fn mirror_flags(&self) -> Mirror {
+        self.raw.mirror_flags
+    }
+
+    /// Returns the maximum width of the input [`Surface`], or 0 if unknown.
+    #[inline]
+    pub fn max_input_width(&self) -> u32 {
+        self.raw.max_input_width
+    }
+
+    /// Returns the maximum height of the input [`Surface`], or 0 if unknown.
+    #[inline]
+    pub fn max_input_height(&self) -> u32 {
+        self.raw.max_input_height
+    }
+
+    /// Returns the minimum width of the input [`Surface`], or 0 if unknown.
+    #[inline]
+    pub fn min_input_width(&self) -> u32 {
+        self.raw.min_input_width
+    }
+
+    /// Returns the minimum height of the input [`Surface`], or 0 if unknown.
+    #[inline]
+    pub fn min_input_height(&self) -> u32 {
+        self.raw.min_input_height
+    }
+
+    /// Returns the maximum width of the output [`Surface`], or 0 if unknown.
+    #[inline]
+    pub fn max_output_width(&self) -> u32 {
+        self.raw.max_output_width
+    }
+
+    /// Returns the maximum height of the output [`Surface`], or 0 if unknown.
+    #[inline]
+    pub fn max_output_height(&self) -> u32 {
+        self.raw.max_output_height
+    }
+
+    /// Returns the minimum width of the output [`Surface`], or 0 if unknown.
+    #[inline]
+    pub fn min_output_width(&self) -> u32 {
+        self.raw.min_output_width
+    }
+
+    /// Returns the minimum height of the output [`Surface`], or 0 if unknown.
+    #[inline]
+    pub fn min_output_height(&self) -> u32 {
+        self.raw.min_output_height
+    }
+
+    /// Checks the operations requested by `params` against these capabilities.
+    ///
+    /// `input_surface` and `output_surface` describe the input [`Surface`] of `params` and the output [`Surface`]
+    /// the operation will write to, whose sizes and [`PixelFormat`]s are checked as well.
+    ///
+    /// Returns the list of requested operations that the driver does not support. An empty list
+    /// means that no unsupported operation was found (the driver may still reject the operation
+    /// for reasons not covered by the capabilities).
+    pub fn validate(
+        &self,
+        params: &ProcPipelineParameterBuffer<'_>,
+        input_surface: ProcSurface,
+        output_surface: ProcSurface,
+    ) -> Vec<UnsupportedOperation> {
+        let mut unsupported = Vec::new();
+
+        let pipeline_flags = params.pipeline_flags().difference(self.pipeline_flags());
+        if !pipeline_flags.is_empty() {
+            unsupported.push(UnsupportedOperation::PipelineFlags(pipeline_flags));
+        }
+
+        let rotation = params.rotation();
+        if rotation != Rotation::NONE {
+            let supported = rotation.0 < 32
+                && self
+                    .rotation_flags()
+                    .contains(RotationFlags::from_bits_retain(1 << rotation.0));
+            if !supported {
+                unsupported.push(UnsupportedOperation::Rotation(rotation));
+            }
+        }
+
+        let mirror = params.mirror().difference(self.mirror_flags());
+        if !mirror.is_empty() {
+            unsupported.push(UnsupportedOperation::Mirror(mirror));
+        }
+
+        if let Some(blend) = params.blend_state() {
+            let flags = blend.flags().difference(self.blend_flags());
+            if !flags.is_empty() {
+                unsupported.push(UnsupportedOperation::Blend(flags));
+            }
+        }
+
+        // An empty list means that the driver didn't report anything.
+        let input = params.input_color_standard();
+        if input != ColorStandardType::None
+            && !self.input_color_standards.is_empty()
+            && !self.input_color_standards.contains(&input)
+        {
+            unsupported.push(UnsupportedOperation::InputColorStandard(input));
+        }
+        let output = params.output_color_standard();
+        if output != ColorStandardType::None
+            && !self.output_color_standards.is_empty()
+            && !self.output_color_standards.contains(&output)
+        {
+            unsupported.push(UnsupportedOperation::OutputColorStandard(output));
+        }
+
+        if params.num_additional_outputs > self.num_additional_outputs() {
+            unsupported.push(UnsupportedOperation::AdditionalOutputs {
+                requested: params.num_additional_outputs,
+                supported: self.num_additional_outputs(),
+            });
+        }
+
+        let min_input = (self.min_input_width(), self.min_input_height());
+        let max_input = (self.max_input_width(), self.max_input_height());
+        let min_output = (self.min_output_width(), self.min_output_height());
+        let max_output = (self.max_output_width(), self.max_output_height());
+        if !size_in_range(
+            input_surface.width,
+            input_surface.height,
+            min_input,
+            max_input,
+        ) {
+            unsupported.push(UnsupportedOperation::InputSize {
+                width: input_surface.width,
+                height: input_surface.height,
+            });
+        }
+        if !size_in_range(
+            output_surface.width,
+            output_surface.height,
+            min_output,
+            max_output,
+        ) {
+            unsupported.push(UnsupportedOperation::OutputSize {
+                width: output_surface.width,
+                height: output_surface.height,
+            });
+        }
+
+        if let Some((x, y, width, height)) = params
+            .surface_region()
+            .and_then(|region| unsupported_region(region, input_surface, min_input, max_input))
+        {
+            unsupported.push(UnsupportedOperation::InputRegion {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
+        if let Some((x, y, width, height)) = params
+            .output_region()
+            .and_then(|region| unsupported_region(region, output_surface, min_output, max_output))
+        {
+            unsupported.push(UnsupportedOperation::OutputRegion {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
+
+        if let (Some(format), Some(formats)) =
+            (input_surface.pixel_format, self.input_pixel_formats())
+        {
+            if !formats.contains(&format) {
+                unsupported.push(UnsupportedOperation::InputPixelFormat(format));
+            }
+        }
+        if let (Some(format), Some(formats)) =
+            (output_surface.pixel_format, self.output_pixel_formats())
+        {
+            if !formats.contains(&format) {
+                unsupported.push(UnsupportedOperation::OutputPixelFormat(format));
+            }
+        }
+
+        unsupported
+    }
+
     #[inline]
     pub fn input_color_standards(&self) -> &[ColorStandardType] {
         &self.input_color_standards
@@ -678,6 +1741,7 @@ mod tests {
         buffer::BufferType,
         config::Config,
         image::{Image, ImageFormat},
+        surface::RTFormat,
         test::*,
         Entrypoint, Profile,
     };
@@ -734,4 +1798,225 @@ mod tests {
             assert_eq!(&map[..TEST_DATA.len()], TEST_DATA);
         });
     }
+
+    #[test]
+    fn validate_caps() {
+        let mut raw: RawProcPipelineCaps = unsafe { mem::zeroed() };
+        raw.rotation_flags = RotationFlags::R180;
+        raw.mirror_flags = Mirror::HORIZONTAL;
+        raw.num_additional_outputs = 1;
+        let caps = ProcPipelineCaps {
+            raw,
+            input_color_standards: vec![ColorStandardType::BT601, ColorStandardType::BT709],
+            output_color_standards: Vec::new(),
+            input_pixel_formats: None,
+            output_pixel_formats: None,
+        };
+        let surface = ProcSurface::new(64, 64);
+
+        let mut params: ProcPipelineParameterBuffer<'_> = unsafe { mem::zeroed() };
+        params.set_rotation(Rotation::R180);
+        params.set_mirror(Mirror::HORIZONTAL);
+        params.set_input_color_standard(ColorStandardType::BT709);
+        params.set_output_color_standard(ColorStandardType::SRGB);
+        assert_eq!(caps.validate(&params, surface, surface), []);
+
+        let blend = BlendState::new(BlendFlags::GLOBAL_ALPHA);
+        params.set_rotation(Rotation::R90);
+        params.set_mirror(Mirror::HORIZONTAL | Mirror::VERTICAL);
+        params.set_blend_state(&blend);
+        params.set_input_color_standard(ColorStandardType::BT2020);
+        params.set_pipeline_flags(PipelineFlags::FAST);
+        params.num_additional_outputs = 2;
+        assert_eq!(
+            caps.validate(&params, surface, surface),
+            [
+                UnsupportedOperation::PipelineFlags(PipelineFlags::FAST),
+                UnsupportedOperation::Rotation(Rotation::R90),
+                UnsupportedOperation::Mirror(Mirror::VERTICAL),
+                UnsupportedOperation::Blend(BlendFlags::GLOBAL_ALPHA),
+                UnsupportedOperation::InputColorStandard(ColorStandardType::BT2020),
+                UnsupportedOperation::AdditionalOutputs {
+                    requested: 2,
+                    supported: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_caps_sizes() {
+        let mut raw: RawProcPipelineCaps = unsafe { mem::zeroed() };
+        raw.min_input_width = 16;
+        raw.min_input_height = 16;
+        raw.max_input_width = 128;
+        raw.max_input_height = 128;
+        raw.min_output_width = 32;
+        raw.min_output_height = 32;
+        // Unknown maximum output size.
+        let caps = ProcPipelineCaps {
+            raw,
+            input_color_standards: Vec::new(),
+            output_color_standards: Vec::new(),
+            input_pixel_formats: None,
+            output_pixel_formats: None,
+        };
+
+        let params: ProcPipelineParameterBuffer<'_> = unsafe { mem::zeroed() };
+        let input = ProcSurface::new(64, 64);
+        let output = ProcSurface::new(4096, 32);
+        assert_eq!(caps.validate(&params, input, output), []);
+        assert_eq!(
+            caps.validate(&params, ProcSurface::new(256, 64), ProcSurface::new(32, 16)),
+            [
+                UnsupportedOperation::InputSize {
+                    width: 256,
+                    height: 64
+                },
+                UnsupportedOperation::OutputSize {
+                    width: 32,
+                    height: 16
+                },
+            ]
+        );
+
+        // Regions must fit into their surface, and have a supported size.
+        let mut params: ProcPipelineParameterBuffer<'_> = unsafe { mem::zeroed() };
+        let input_region = Rectangle::new(8, 8, 56, 56);
+        let output_region = Rectangle::new(0, 0, 64, 32);
+        params.set_surface_region(&input_region);
+        params.output_region = &output_region;
+        assert_eq!(caps.validate(&params, input, output), []);
+
+        let input_region = Rectangle::new(16, 8, 56, 56);
+        let output_region = Rectangle::new(0, 0, 16, 32);
+        params.set_surface_region(&input_region);
+        params.output_region = &output_region;
+        assert_eq!(
+            caps.validate(&params, input, output),
+            [
+                UnsupportedOperation::InputRegion {
+                    x: 16,
+                    y: 8,
+                    width: 56,
+                    height: 56
+                },
+                UnsupportedOperation::OutputRegion {
+                    x: 0,
+                    y: 0,
+                    width: 16,
+                    height: 32
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_caps_pixel_formats() {
+        let raw: RawProcPipelineCaps = unsafe { mem::zeroed() };
+        let caps = ProcPipelineCaps {
+            raw,
+            input_color_standards: Vec::new(),
+            output_color_standards: Vec::new(),
+            input_pixel_formats: Some(vec![PixelFormat::NV12, PixelFormat::P010]),
+            output_pixel_formats: Some(vec![PixelFormat::RGBA]),
+        };
+
+        let params: ProcPipelineParameterBuffer<'_> = unsafe { mem::zeroed() };
+        let nv12 = ProcSurface::new(64, 64).with_pixel_format(PixelFormat::NV12);
+        let rgba = ProcSurface::new(64, 64).with_pixel_format(PixelFormat::RGBA);
+        assert_eq!(caps.validate(&params, nv12, rgba), []);
+        // Surfaces with unknown formats are not checked.
+        let unknown = ProcSurface::new(64, 64);
+        assert_eq!(caps.validate(&params, unknown, unknown), []);
+        assert_eq!(
+            caps.validate(&params, rgba, nv12),
+            [
+                UnsupportedOperation::InputPixelFormat(PixelFormat::RGBA),
+                UnsupportedOperation::OutputPixelFormat(PixelFormat::NV12),
+            ]
+        );
+    }
+
+    #[test]
+    fn additional_outputs_size() {
+        run_test(|display| {
+            let mut raw: RawProcPipelineCaps = unsafe { mem::zeroed() };
+            raw.num_additional_outputs = 2;
+            raw.min_output_width = TEST_WIDTH;
+            raw.min_output_height = TEST_HEIGHT;
+            raw.max_output_width = TEST_WIDTH * 2;
+            let caps = ProcPipelineCaps {
+                raw,
+                input_color_standards: Vec::new(),
+                output_color_standards: Vec::new(),
+                input_pixel_formats: None,
+                output_pixel_formats: None,
+            };
+
+            let mut small = Surface::new(display, 8, 8, RTFormat::YUV420).unwrap();
+            let mut large = Surface::new(display, 64, 64, RTFormat::YUV420).unwrap();
+            let mut fitting = Surface::new(display, 32, 64, RTFormat::YUV420).unwrap();
+            let mut outputs = AdditionalOutputs::new(&caps);
+            assert!(outputs.push(&mut small, 8, 8).is_err());
+            assert!(outputs.push(&mut large, 64, 64).is_err());
+            outputs.push(&mut fitting, 32, 64).unwrap();
+            assert_eq!(outputs.len(), 1);
+        });
+    }
+
+    #[test]
+    fn vpp_additional_outputs() {
+        run_test(|display| {
+            let input_surface = test_surface(display);
+
+            let config = Config::new(display, Profile::None, Entrypoint::VideoProc).unwrap();
+            let mut context = Context::new(&config, TEST_WIDTH, TEST_HEIGHT).unwrap();
+            let caps = context
+                .query_video_processing_pipeline_caps(&mut Filters::new())
+                .unwrap();
+            if caps.num_additional_outputs() == 0 {
+                eprintln!("(skipping test; additional outputs are not supported)");
+                return;
+            }
+
+            let mut output_surface =
+                Surface::with_pixel_format(display, TEST_WIDTH, TEST_HEIGHT, TEST_PIXELFORMAT)
+                    .unwrap();
+            let mut extra_surface =
+                Surface::with_pixel_format(display, TEST_WIDTH, TEST_HEIGHT, TEST_PIXELFORMAT)
+                    .unwrap();
+
+            let mut outputs = AdditionalOutputs::new(&caps);
+            outputs
+                .push(&mut extra_surface, TEST_WIDTH, TEST_HEIGHT)
+                .unwrap();
+            let mut pppbuf = ProcPipelineParameterBuffer::new(&input_surface);
+            pppbuf.set_additional_outputs(&outputs);
+
+            let mut params =
+                Buffer::new_param(&context, BufferType::ProcPipelineParameter, pppbuf).unwrap();
+
+            let mut picture = context.begin_picture(&mut output_surface).unwrap();
+            unsafe {
+                picture.render_picture(&mut params).unwrap();
+                picture.end_picture().unwrap();
+            }
+            drop(params);
+
+            for surface in [&mut output_surface, &mut extra_surface] {
+                let mut image = Image::new(
+                    display,
+                    ImageFormat::new(TEST_PIXELFORMAT),
+                    TEST_WIDTH,
+                    TEST_HEIGHT,
+                )
+                .unwrap();
+                surface.sync().unwrap();
+                surface.copy_to_image(&mut image).unwrap();
+                let map = image.map().unwrap();
+                assert_eq!(&map[..TEST_DATA.len()], TEST_DATA);
+            }
+        });
+    }
 }
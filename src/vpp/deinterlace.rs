@@ -0,0 +1,354 @@
+use std::{collections::VecDeque, mem};
+
+use crate::{
+    buffer::{Buffer, BufferType},
+    config::Config,
+    context::Context,
+    display::Display,
+    surface::Surface,
+    Entrypoint, Error, Profile, Result,
+};
+
+use super::{
+    DeinterlacingFlags, DeinterlacingParameterBuffer, DeinterlacingType, FilterFlags, Filters,
+    ProcPipelineParameterBuffer, References,
+};
+
+/// Deinterlaces a stream of interlaced frames, using past and future frames as references.
+///
+/// Each interlaced input frame is turned into two progressive output frames (one per field), so
+/// the output has twice the frame rate of the input.
+///
+/// The [`Deinterlacer`] keeps a ring of input frames around, sized according to the number of
+/// forward and backward references the driver requests for the chosen [`DeinterlacingType`].
+/// Because of the backward (future) references, output lags behind input by
+/// [`Deinterlacer::num_backward_references`] frames. At the end of the stream, the remaining
+/// frames are output by calling [`Deinterlacer::drain_frame`].
+pub struct Deinterlacer {
+    context: Context,
+    algorithm: DeinterlacingType,
+    top_field_first: bool,
+    window: ReferenceWindow<Surface>,
+}
+
+/// Tracks the current frame and its references in a stream of frames.
+///
+/// This is generic over the frame type so that the bookkeeping can be tested without a driver.
+struct ReferenceWindow<T> {
+    num_forward: usize,
+    num_backward: usize,
+    frames: VecDeque<T>,
+    /// Number of times the window has been advanced without pushing a frame, at the end of the
+    /// stream.
+    drained: usize,
+}
+
+impl<T> ReferenceWindow<T> {
+    fn new(num_forward: usize, num_backward: usize) -> Self {
+        Self {
+            num_forward,
+            num_backward,
+            frames: VecDeque::with_capacity(num_forward + num_backward + 1),
+            drained: 0,
+        }
+    }
+
+    /// Returns the index of the current frame in `frames`, or [`None`] if there is none.
+    fn current(&self) -> Option<usize> {
+        (self.frames.len() + self.drained)
+            .checked_sub(self.num_backward + 1)
+            .filter(|&current| current < self.frames.len())
+    }
+
+    fn push(&mut self, frame: T) -> Option<T> {
+        assert_eq!(self.drained, 0, "cannot push frames while draining");
+        self.frames.push_back(frame);
+        self.evict()
+    }
+
+    /// Advances the current frame without pushing a new one.
+    ///
+    /// Returns `false` if all frames have already been the current frame.
+    fn drain(&mut self) -> (bool, Option<T>) {
+        if self.drained > self.num_backward || self.frames.is_empty() {
+            return (false, None);
+        }
+        self.drained += 1;
+        (true, self.evict())
+    }
+
+    /// Removes the oldest frame if it is no longer needed as a forward reference.
+    fn evict(&mut self) -> Option<T> {
+        let current = (self.frames.len() + self.drained).saturating_sub(self.num_backward + 1);
+        if current > self.num_forward {
+            self.frames.pop_front()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the forward references of the current frame, from nearest to farthest in time.
+    fn forward(&self, current: usize) -> impl Iterator<Item = &T> {
+        self.frames.range(..current).rev().take(self.num_forward)
+    }
+
+    /// Returns the backward references of the current frame, from nearest to farthest in time.
+    fn backward(&self, current: usize) -> impl Iterator<Item = &T> {
+        self.frames.range(current + 1..)
+    }
+
+    fn reset(&mut self) -> VecDeque<T> {
+        self.drained = 0;
+        mem::take(&mut self.frames)
+    }
+}
+
+impl Deinterlacer {
+    /// Creates a [`Deinterlacer`] for frames of the given size.
+    ///
+    /// `algorithm` is typically [`DeinterlacingType::MotionAdaptive`] or
+    /// [`DeinterlacingType::MotionCompensated`]. `top_field_first` specifies the field order of
+    /// the input frames.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the driver does not support video processing, or does not support the
+    /// requested deinterlacing `algorithm`.
+    pub fn new(
+        display: &Display,
+        width: u32,
+        height: u32,
+        algorithm: DeinterlacingType,
+        top_field_first: bool,
+    ) -> Result<Self> {
+        let config = Config::new(display, Profile::None, Entrypoint::VideoProc)?;
+        let context = Context::new(&config, width, height)?;
+
+        let supported = context.query_deinterlacing_caps()?;
+        if !supported.contains(&algorithm) {
+            return Err(Error::from(format!(
+                "deinterlacing algorithm {algorithm:?} is not supported (supported: {supported:?})"
+            )));
+        }
+
+        let mut filters = Filters::new();
+        filters.push(Buffer::new_param(
+            &context,
+            BufferType::ProcFilterParameter,
+            DeinterlacingParameterBuffer::new(algorithm, DeinterlacingFlags::empty()),
+        )?);
+        let caps = context.query_video_processing_pipeline_caps(&mut filters)?;
+        let num_forward_references = caps.num_forward_references() as usize;
+        let num_backward_references = caps.num_backward_references() as usize;
+        log::debug!(
+            "{algorithm:?} deinterlacing uses {num_forward_references} forward and \
+            {num_backward_references} backward references"
+        );
+
+        Ok(Self {
+            context,
+            algorithm,
+            top_field_first,
+            window: ReferenceWindow::new(num_forward_references, num_backward_references),
+        })
+    }
+
+    /// Returns the number of past frames used as references.
+    #[inline]
+    pub fn num_forward_references(&self) -> usize {
+        self.window.num_forward
+    }
+
+    /// Returns the number of future frames used as references.
+    #[inline]
+    pub fn num_backward_references(&self) -> usize {
+        self.window.num_backward
+    }
+
+    /// Pushes a decoded interlaced frame into the reference ring.
+    ///
+    /// If the ring is full, the oldest frame is removed from it and returned, so that its
+    /// [`Surface`] can be reused. The returned [`Surface`] has been synced, so no pending
+    /// deinterlacing operation is still reading from it.
+    ///
+    /// If the [`Deinterlacer`] was being drained (see [`Deinterlacer::drain_frame`]), this starts
+    /// a new stream, and the frames of the previous stream are dropped. Use
+    /// [`Deinterlacer::reset`] first to reuse them instead.
+    pub fn push_frame(&mut self, frame: Surface) -> Result<Option<Surface>> {
+        if self.window.drained != 0 {
+            self.reset()?;
+        }
+        self.window.push(frame).map(sync_evicted).transpose()
+    }
+
+    /// Advances to the next frame without pushing a new one, at the end of the stream.
+    ///
+    /// Once the last frame has been pushed, the [`Deinterlacer::num_backward_references`] most
+    /// recent frames have not been output yet. Calling this method until it returns `false`, and
+    /// [`Deinterlacer::deinterlace`] after every call that returns `true`, outputs them using
+    /// fewer backward references.
+    ///
+    /// Any frame that is no longer needed is synced and dropped. [`Deinterlacer::reset`] returns
+    /// the remaining frames.
+    pub fn drain_frame(&mut self) -> Result<bool> {
+        loop {
+            let (advanced, evicted) = self.window.drain();
+            if let Some(frame) = evicted {
+                sync_evicted(frame)?;
+            }
+            if !advanced {
+                return Ok(false);
+            }
+            if self.is_ready() {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Removes all frames from the reference ring, and returns them so that their [`Surface`]s
+    /// can be reused.
+    ///
+    /// The returned [`Surface`]s have been synced. The next pushed frame starts a new stream.
+    pub fn reset(&mut self) -> Result<Vec<Surface>> {
+        self.window.reset().into_iter().map(sync_evicted).collect()
+    }
+
+    /// Returns whether enough frames have been pushed to deinterlace the current frame.
+    ///
+    /// The current frame is the frame that was pushed [`Deinterlacer::num_backward_references`]
+    /// frames ago. When the ring is not yet full, fewer forward references will be used.
+    #[inline]
+    pub fn is_ready(&self) -> bool {
+        self.window.current().is_some()
+    }
+
+    /// Deinterlaces the current frame, writing its first field to `first` and its second field to
+    /// `second`.
+    ///
+    /// The output [`Surface`]s contain progressive frames and should be displayed in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if not enough frames have been pushed yet (see
+    /// [`Deinterlacer::is_ready`]), or if the VA-API operation fails.
+    pub fn deinterlace(&mut self, first: &mut Surface, second: &mut Surface) -> Result<()> {
+        let Some(current) = self.window.current() else {
+            return Err(Error::from(format!(
+                "deinterlacer needs {} more frame(s) before output can be produced",
+                (self.window.num_backward + 1).saturating_sub(self.window.frames.len()),
+            )));
+        };
+
+        let mut forward = References::new();
+        for frame in self.window.forward(current) {
+            forward.push(frame);
+        }
+        let mut backward = References::new();
+        for frame in self.window.backward(current) {
+            backward.push(frame);
+        }
+
+        for (index, output) in [first, second].into_iter().enumerate() {
+            let bottom_field = (index == 0) != self.top_field_first;
+
+            let mut flags = DeinterlacingFlags::empty();
+            if !self.top_field_first {
+                flags |= DeinterlacingFlags::BOTTOM_FIELD_FIRST;
+            }
+            if bottom_field {
+                flags |= DeinterlacingFlags::BOTTOM_FIELD;
+            }
+
+            let mut filters = Filters::new();
+            filters.push(Buffer::new_param(
+                &self.context,
+                BufferType::ProcFilterParameter,
+                DeinterlacingParameterBuffer::new(self.algorithm, flags),
+            )?);
+
+            let mut pppbuf = ProcPipelineParameterBuffer::new(&self.window.frames[current]);
+            pppbuf.set_filters(&mut filters);
+            pppbuf.set_filter_flags(if bottom_field {
+                FilterFlags::BOTTOM_FIELD
+            } else {
+                FilterFlags::TOP_FIELD
+            });
+            pppbuf.set_forward_references(&forward);
+            pppbuf.set_backward_references(&backward);
+
+            let mut params =
+                Buffer::new_param(&self.context, BufferType::ProcPipelineParameter, pppbuf)?;
+
+            let mut picture = self.context.begin_picture(output)?;
+            unsafe {
+                picture.render_picture(&mut params)?;
+                picture.end_picture()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Waits until `frame` is no longer read by a deinterlacing operation.
+fn sync_evicted(mut frame: Surface) -> Result<Surface> {
+    frame.sync()?;
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window_state(window: &ReferenceWindow<u32>) -> Option<(Vec<u32>, u32, Vec<u32>)> {
+        let current = window.current()?;
+        Some((
+            window.forward(current).copied().collect(),
+            window.frames[current],
+            window.backward(current).copied().collect(),
+        ))
+    }
+
+    #[test]
+    fn reference_window() {
+        let mut window = ReferenceWindow::new(2, 1);
+        assert_eq!(window_state(&window), None);
+
+        assert_eq!(window.push(0), None);
+        assert_eq!(window_state(&window), None);
+        assert_eq!(window.push(1), None);
+        assert_eq!(window_state(&window), Some((vec![], 0, vec![1])));
+        assert_eq!(window.push(2), None);
+        assert_eq!(window_state(&window), Some((vec![0], 1, vec![2])));
+        assert_eq!(window.push(3), None);
+        assert_eq!(window_state(&window), Some((vec![1, 0], 2, vec![3])));
+        assert_eq!(window.push(4), Some(0));
+        assert_eq!(window_state(&window), Some((vec![2, 1], 3, vec![4])));
+
+        // Draining outputs the last frame without backward references, then stops.
+        assert_eq!(window.drain(), (true, Some(1)));
+        assert_eq!(window_state(&window), Some((vec![3, 2], 4, vec![])));
+        assert_eq!(window.drain(), (true, Some(2)));
+        assert_eq!(window_state(&window), None);
+        assert_eq!(window.drain(), (false, None));
+
+        assert_eq!(window.reset(), [3, 4]);
+        assert_eq!(window.push(5), None);
+        assert_eq!(window_state(&window), None);
+    }
+
+    #[test]
+    fn reference_window_short_stream() {
+        // A stream shorter than the number of backward references.
+        let mut window = ReferenceWindow::new(1, 2);
+        assert_eq!(window.push(0), None);
+        assert_eq!(window_state(&window), None);
+        assert_eq!(window.drain(), (true, None));
+        assert_eq!(window_state(&window), None);
+        assert_eq!(window.drain(), (true, None));
+        assert_eq!(window_state(&window), Some((vec![], 0, vec![])));
+        assert_eq!(window.drain(), (true, None));
+        assert_eq!(window_state(&window), None);
+        assert_eq!(window.drain(), (false, None));
+    }
+}
@@ -0,0 +1,398 @@
+use std::sync::Arc;
+
+use crate::{
+    buffer::{Buffer, BufferType},
+    context::Context,
+    display::Display,
+    image::{Image, ImageFormat},
+    surface::Surface,
+    Error, PixelFormat, Result,
+};
+
+use super::{Filters, Lut3DChannelMapping, Lut3DParameterBuffer};
+
+/// Largest `LUT_3D_SIZE` accepted when parsing `.cube` files.
+const MAX_CUBE_SIZE: u32 = 256;
+
+/// A 3D color lookup table, stored on the CPU.
+///
+/// Can be loaded from Adobe/Resolve `.cube` files with [`Lut3D::from_cube`], and uploaded to the
+/// GPU with [`Lut3DFilter::new`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut3D {
+    title: Option<String>,
+    size: u32,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    /// `size³` RGB entries, with the red index changing fastest (like in `.cube` files).
+    data: Vec<[f32; 3]>,
+}
+
+impl Lut3D {
+    /// Creates a [`Lut3D`] with `size` points along each axis from a list of RGB entries.
+    ///
+    /// `data` must contain `size³` entries, ordered with the red index changing fastest, followed
+    /// by green, then blue (the same order used by `.cube` files).
+    pub fn new(size: u32, data: Vec<[f32; 3]>) -> Result<Self> {
+        if size < 2 {
+            return Err(Error::from(format!("invalid 3D LUT size {size}")));
+        }
+        let expected = (size as usize).pow(3);
+        if data.len() != expected {
+            return Err(Error::from(format!(
+                "3D LUT of size {size} needs {expected} entries, got {}",
+                data.len()
+            )));
+        }
+        Ok(Self {
+            title: None,
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            data,
+        })
+    }
+
+    /// Parses the contents of a `.cube` file.
+    ///
+    /// Only 3D LUTs are supported; files containing a `LUT_1D_SIZE` keyword are rejected.
+    pub fn from_cube(text: &str) -> Result<Self> {
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut data = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let lineno = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            match keyword {
+                "TITLE" => {
+                    title = Some(rest.trim_matches('"').to_string());
+                }
+                "LUT_3D_SIZE" => {
+                    if size.is_some() {
+                        return Err(cube_error(lineno, "duplicate LUT_3D_SIZE"));
+                    }
+                    let n: u32 = rest
+                        .parse()
+                        .map_err(|_| cube_error(lineno, format!("invalid LUT_3D_SIZE `{rest}`")))?;
+                    if !(2..=MAX_CUBE_SIZE).contains(&n) {
+                        let msg = format!("LUT_3D_SIZE {n} out of range");
+                        return Err(cube_error(lineno, msg));
+                    }
+                    data.reserve((n as usize).pow(3));
+                    size = Some(n);
+                }
+                "LUT_1D_SIZE" => {
+                    return Err(cube_error(lineno, "1D LUTs are not supported"));
+                }
+                "DOMAIN_MIN" => domain_min = parse_triple(lineno, rest)?,
+                "DOMAIN_MAX" => domain_max = parse_triple(lineno, rest)?,
+                "LUT_3D_INPUT_RANGE" => {
+                    let [min, max] = parse_floats(lineno, rest)?;
+                    domain_min = [min; 3];
+                    domain_max = [max; 3];
+                }
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    log::debug!("ignoring unknown .cube keyword `{keyword}` on line {lineno}");
+                }
+                _ => {
+                    let Some(n) = size else {
+                        return Err(cube_error(lineno, "table data before LUT_3D_SIZE"));
+                    };
+                    if data.len() == (n as usize).pow(3) {
+                        return Err(cube_error(lineno, "too many table entries"));
+                    }
+                    data.push(parse_triple(lineno, line)?);
+                }
+            }
+        }
+
+        let Some(size) = size else {
+            return Err(Error::from("missing LUT_3D_SIZE in .cube file"));
+        };
+        for axis in 0..3 {
+            if domain_min[axis] >= domain_max[axis] {
+                return Err(Error::from(format!(
+                    "invalid .cube domain {domain_min:?}..{domain_max:?}"
+                )));
+            }
+        }
+
+        let mut lut = Self::new(size, data)?;
+        lut.title = title;
+        lut.domain_min = domain_min;
+        lut.domain_max = domain_max;
+        Ok(lut)
+    }
+
+    /// Returns the title of the LUT, if the `.cube` file specified one.
+    #[inline]
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Returns the number of points along each axis of the LUT.
+    #[inline]
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Returns the minimum input value of each channel (`DOMAIN_MIN`).
+    #[inline]
+    pub fn domain_min(&self) -> [f32; 3] {
+        self.domain_min
+    }
+
+    /// Returns the maximum input value of each channel (`DOMAIN_MAX`).
+    #[inline]
+    pub fn domain_max(&self) -> [f32; 3] {
+        self.domain_max
+    }
+
+    /// Returns the output color stored at the given grid point.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any index is out of bounds.
+    pub fn get(&self, r: u32, g: u32, b: u32) -> [f32; 3] {
+        assert!(r < self.size && g < self.size && b < self.size);
+        let n = self.size as usize;
+        self.data[r as usize + g as usize * n + b as usize * n * n]
+    }
+
+    /// Converts the LUT to 16-bit RGBA entries, laid out as `lut[r][g][b]` with the given strides.
+    ///
+    /// Values are clamped to the 0.0-1.0 range. Padding entries are left zeroed.
+    fn to_rgba16(&self, stride: [u16; 3]) -> Vec<[u16; 4]> {
+        let [s0, s1, s2] = stride.map(usize::from);
+        let mut out = vec![[0; 4]; s0 * s1 * s2];
+        for r in 0..self.size {
+            for g in 0..self.size {
+                for b in 0..self.size {
+                    let rgb = self
+                        .get(r, g, b)
+                        .map(|v| (v.clamp(0.0, 1.0) * 65535.0).round());
+                    let index = (r as usize * s1 + g as usize) * s2 + b as usize;
+                    out[index] = [rgb[0] as u16, rgb[1] as u16, rgb[2] as u16, 0xffff];
+                }
+            }
+        }
+        out
+    }
+}
+
+fn cube_error(lineno: usize, msg: impl std::fmt::Display) -> Error {
+    Error::from(format!("line {lineno} of .cube file: {msg}"))
+}
+
+fn parse_floats<const N: usize>(lineno: usize, s: &str) -> Result<[f32; N]> {
+    let mut out = [0.0; N];
+    let mut parts = s.split_whitespace();
+    for value in &mut out {
+        let part = parts
+            .next()
+            .ok_or_else(|| cube_error(lineno, format!("expected {N} values")))?;
+        *value = part
+            .parse()
+            .map_err(|_| cube_error(lineno, format!("invalid number `{part}`")))?;
+    }
+    if parts.next().is_some() {
+        return Err(cube_error(lineno, format!("expected {N} values")));
+    }
+    Ok(out)
+}
+
+fn parse_triple(lineno: usize, s: &str) -> Result<[f32; 3]> {
+    parse_floats(lineno, s)
+}
+
+/// A [`Lut3D`] uploaded to the GPU, ready to be used as a video processing filter.
+///
+/// The table is stored in a [`Surface`] in the layout described by the matching
+/// [`Lut3DCap`][super::Lut3DCap]: 16-bit RGBA entries, indexed as `lut[r][g][b]`. Uploading is
+/// relatively expensive, so a [`Lut3DFilter`] should be created once and reused for every frame.
+pub struct Lut3DFilter {
+    surface: Arc<Surface>,
+    params: Lut3DParameterBuffer,
+}
+
+impl Lut3DFilter {
+    /// Uploads `lut` for use with the video processing [`Context`] `cx`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the driver does not support the [`FilterType::LUT3D`] filter, or no
+    /// supported configuration matches the size of `lut`, or if the LUT uses a non-default input
+    /// domain.
+    ///
+    /// [`FilterType::LUT3D`]: super::FilterType::LUT3D
+    pub fn new(display: &Display, cx: &Context, lut: &Lut3D) -> Result<Self> {
+        if lut.domain_min != [0.0; 3] || lut.domain_max != [1.0; 3] {
+            return Err(Error::from(format!(
+                "3D LUTs with a custom input domain ({:?}..{:?}) are not supported",
+                lut.domain_min, lut.domain_max
+            )));
+        }
+
+        let caps = cx.query_lut3d_caps()?;
+        let cap = caps
+            .iter()
+            .find(|cap| {
+                u32::from(cap.lut_size()) == lut.size()
+                    && cap.bit_depth() == 16
+                    && cap.num_channel() == 4
+                    && cap.channel_mapping().contains(Lut3DChannelMapping::RGB_RGB)
+            })
+            .ok_or_else(|| {
+                Error::from(format!(
+                    "3D LUT of size {} is not supported (supported: {:?})",
+                    lut.size(),
+                    caps,
+                ))
+            })?;
+
+        let stride = cap.lut_stride();
+        // Every 8-byte entry occupies 2 RGBA pixels.
+        let width = u32::from(stride[2]) * 2;
+        let height = u32::from(stride[0]) * u32::from(stride[1]);
+        let mut surface = Surface::with_pixel_format(display, width, height, PixelFormat::RGBA)?;
+        let mut image = Image::new(display, ImageFormat::new(PixelFormat::RGBA), width, height)?;
+
+        let entries = lut.to_rgba16(stride);
+        let row_len = usize::from(stride[2]);
+        let pitch = image.raw.pitches[0] as usize;
+        let offset = image.raw.offsets[0] as usize;
+        {
+            let mut map = image.map()?;
+            for (row, entries) in entries.chunks(row_len).enumerate() {
+                let bytes: &[u8] = bytemuck::cast_slice(entries);
+                let start = offset + row * pitch;
+                map[start..start + bytes.len()].copy_from_slice(bytes);
+            }
+        }
+        surface.copy_from_image(&mut image)?;
+
+        let params = Lut3DParameterBuffer::new(&surface, cap);
+        Ok(Self {
+            surface: Arc::new(surface),
+            params,
+        })
+    }
+
+    /// Returns the [`Surface`] storing the uploaded table.
+    #[inline]
+    pub fn surface(&self) -> &Surface {
+        &self.surface
+    }
+}
+
+impl Filters {
+    /// Appends a 3D LUT filter to the end of the filter chain.
+    ///
+    /// The [`Filters`] keep the uploaded table alive, so `lut` can be dropped afterwards.
+    pub fn push_lut3d(&mut self, cx: &Context, lut: &Lut3DFilter) -> Result<()> {
+        self.push(Buffer::new_param(
+            cx,
+            BufferType::ProcFilterParameter,
+            lut.params,
+        )?);
+        self.lut_surfaces.push(lut.surface.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY_2: &str = "\
+# Created by hand
+TITLE \"Identity\"
+LUT_3D_SIZE 2
+
+0.0 0.0 0.0
+1.0 0.0 0.0
+0.0 1.0 0.0
+1.0 1.0 0.0
+0.0 0.0 1.0
+1.0 0.0 1.0
+0.0 1.0 1.0
+1.0 1.0 1.0
+";
+
+    #[test]
+    fn parse_identity() {
+        let lut = Lut3D::from_cube(IDENTITY_2).unwrap();
+        assert_eq!(lut.title(), Some("Identity"));
+        assert_eq!(lut.size(), 2);
+        assert_eq!(lut.domain_min(), [0.0; 3]);
+        assert_eq!(lut.domain_max(), [1.0; 3]);
+        for r in 0..2 {
+            for g in 0..2 {
+                for b in 0..2 {
+                    assert_eq!(lut.get(r, g, b), [r as f32, g as f32, b as f32]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parse_domain() {
+        let text = IDENTITY_2.replace("LUT_3D_SIZE 2", "LUT_3D_SIZE 2\nDOMAIN_MAX 2 2 2");
+        let lut = Lut3D::from_cube(&text).unwrap();
+        assert_eq!(lut.domain_max(), [2.0; 3]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |text: &str| Lut3D::from_cube(text).unwrap_err().to_string();
+
+        assert_eq!(err(""), "missing LUT_3D_SIZE in .cube file");
+        assert_eq!(
+            err("LUT_1D_SIZE 1024\n"),
+            "line 1 of .cube file: 1D LUTs are not supported"
+        );
+        assert_eq!(
+            err("0 0 0\nLUT_3D_SIZE 2\n"),
+            "line 1 of .cube file: table data before LUT_3D_SIZE"
+        );
+        assert_eq!(
+            err("LUT_3D_SIZE 2\n0 0\n"),
+            "line 2 of .cube file: expected 3 values"
+        );
+        assert_eq!(
+            err("LUT_3D_SIZE 2\n0 0 x\n"),
+            "line 2 of .cube file: invalid number `x`"
+        );
+        assert_eq!(
+            err("LUT_3D_SIZE 2\n0 0 0\n"),
+            "3D LUT of size 2 needs 8 entries, got 1"
+        );
+        assert_eq!(
+            err(&format!("{IDENTITY_2}1 1 1\n")),
+            "line 13 of .cube file: too many table entries"
+        );
+    }
+
+    #[test]
+    fn rgba16_layout() {
+        let lut = Lut3D::from_cube(IDENTITY_2).unwrap();
+        let entries = lut.to_rgba16([2, 2, 4]);
+        assert_eq!(entries.len(), 16);
+        // lut[r][g][b] with the innermost (blue) axis padded to 4 entries.
+        assert_eq!(entries[0], [0, 0, 0, 0xffff]);
+        assert_eq!(entries[1], [0, 0, 0xffff, 0xffff]);
+        assert_eq!(entries[2], [0; 4]);
+        assert_eq!(entries[4], [0, 0xffff, 0, 0xffff]);
+        assert_eq!(entries[8], [0xffff, 0, 0, 0xffff]);
+        assert_eq!(entries[13], [0xffff, 0xffff, 0xffff, 0xffff]);
+    }
+}
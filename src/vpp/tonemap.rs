@@ -0,0 +1,120 @@
+use crate::{
+    buffer::{Buffer, BufferType},
+    config::Config,
+    context::Context,
+    display::Display,
+    surface::Surface,
+    Entrypoint, Error, Profile, Result,
+};
+
+use super::{
+    ColorProperties, ColorStandardType, FilterType, Filters, Hdr10Metadata,
+    HighDynamicRangeMetadataType, ProcPipelineParameterBuffer, SourceRange, ToneMapping,
+};
+
+// ITU-T H.273 code points.
+const PRIMARIES_BT709: u8 = 1;
+const PRIMARIES_BT2020: u8 = 9;
+const TRANSFER_BT709: u8 = 1;
+const TRANSFER_PQ: u8 = 16;
+const MATRIX_BT709: u8 = 1;
+const MATRIX_BT2020_NCL: u8 = 9;
+
+/// Tone maps HDR10 video (BT.2020 primaries, PQ transfer function, 10-bit) to SDR BT.709 on the
+/// GPU.
+///
+/// Input [`Surface`]s typically use [`PixelFormat::P010`][crate::PixelFormat::P010], while the
+/// output [`Surface`] can use any 8-bit format supported by the driver's video processor (eg.
+/// [`PixelFormat::NV12`][crate::PixelFormat::NV12]).
+pub struct ToneMapper {
+    context: Context,
+    output_range: SourceRange,
+}
+
+impl ToneMapper {
+    /// Creates a [`ToneMapper`] for frames of the given size.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the driver does not support video processing, or does not support
+    /// tone mapping HDR10 content to SDR.
+    pub fn new(display: &Display, width: u32, height: u32) -> Result<Self> {
+        let config = Config::new(display, Profile::None, Entrypoint::VideoProc)?;
+        let context = Context::new(&config, width, height)?;
+
+        if !context
+            .query_video_processing_filters()?
+            .contains(FilterType::HighDynamicRangeToneMapping)
+        {
+            return Err(Error::from("HDR tone mapping filter is not supported"));
+        }
+
+        let caps = context.query_hdr_tone_mapping_caps()?;
+        log::debug!("HDR tone mapping caps: {caps:?}");
+        if !caps.iter().any(|cap| {
+            cap.metadata_type() == HighDynamicRangeMetadataType::HDR10
+                && cap.tone_mapping().contains(ToneMapping::HDR_TO_SDR)
+        }) {
+            return Err(Error::from(
+                "HDR10 to SDR tone mapping is not supported by the driver",
+            ));
+        }
+
+        Ok(Self {
+            context,
+            output_range: SourceRange::REDUCED,
+        })
+    }
+
+    /// Sets the color range of the output [`Surface`].
+    ///
+    /// Defaults to [`SourceRange::REDUCED`], which is appropriate for YUV output. When writing to
+    /// an RGB [`Surface`], [`SourceRange::FULL`] should be used instead.
+    #[inline]
+    pub fn set_output_range(&mut self, range: SourceRange) {
+        self.output_range = range;
+    }
+
+    /// Tone maps `input` to SDR and writes the result to `output`.
+    ///
+    /// `metadata` is the HDR10 static metadata of the input video stream, typically taken from the
+    /// bitstream's mastering display and content light level SEI messages.
+    ///
+    /// This method only submits the operation. Call [`Surface::sync`] on `output` before reading
+    /// the result.
+    pub fn tone_map(
+        &mut self,
+        input: &Surface,
+        metadata: &Hdr10Metadata,
+        output: &mut Surface,
+    ) -> Result<()> {
+        let mut filters = Filters::new();
+        filters.push_hdr_tone_mapping(&self.context, *metadata)?;
+
+        let mut pppbuf = ProcPipelineParameterBuffer::new(input);
+        pppbuf.set_filters(&mut filters);
+        pppbuf.set_input_color_standard(ColorStandardType::Explicit);
+        pppbuf.set_input_color_properties(
+            ColorProperties::new()
+                .with_color_range(SourceRange::REDUCED)
+                .with_explicit(PRIMARIES_BT2020, TRANSFER_PQ, MATRIX_BT2020_NCL),
+        );
+        pppbuf.set_output_color_standard(ColorStandardType::Explicit);
+        pppbuf.set_output_color_properties(
+            ColorProperties::new()
+                .with_color_range(self.output_range)
+                .with_explicit(PRIMARIES_BT709, TRANSFER_BT709, MATRIX_BT709),
+        );
+
+        let mut params =
+            Buffer::new_param(&self.context, BufferType::ProcPipelineParameter, pppbuf)?;
+
+        let mut picture = self.context.begin_picture(output)?;
+        unsafe {
+            picture.render_picture(&mut params)?;
+            picture.end_picture()?;
+        }
+
+        Ok(())
+    }
+}